@@ -0,0 +1,444 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    rc::Rc,
+};
+
+use libosu::prelude::*;
+
+use crate::{
+    judgement::{HitResult, ObjectJudgement, SliderEventKind},
+    slider::SliderTiming,
+    BeatmapData,
+};
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const CHANNELS: usize = 2;
+
+/// Decodes any audio file ffmpeg understands into interleaved stereo samples at `SAMPLE_RATE`.
+pub fn decode(path: &Path) -> io::Result<Vec<f32>> {
+    let output = Command::new("ffmpeg")
+        .arg("-i")
+        .arg(path)
+        .args([
+            "-f",
+            "f32le",
+            "-ac",
+            &CHANNELS.to_string(),
+            "-ar",
+            &SAMPLE_RATE.to_string(),
+            "pipe:1",
+        ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("ffmpeg couldn't decode {}", path.display()),
+        ));
+    }
+    Ok(output
+        .stdout
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+/// Stereo track covering `start_ms..end_ms` of the beatmap that sounds are mixed into.
+/// The track plays in real time, so with a `clock_rate` other than 1 sounds are placed
+/// closer together or further apart without changing the sounds themselves.
+pub struct Mixer {
+    start_ms: i32,
+    clock_rate: f64,
+    buffer: Vec<f32>,
+}
+
+impl Mixer {
+    pub fn new(start_ms: i32, end_ms: i32, clock_rate: f64) -> Self {
+        let length_ms = ((end_ms - start_ms) as f64 / clock_rate) as i32;
+        Self {
+            start_ms,
+            clock_rate,
+            buffer: vec![0.0; ms_to_frame(length_ms) * CHANNELS],
+        }
+    }
+
+    /// Adds `samples` starting at beatmap time `time_ms`, cutting off anything outside the track.
+    pub fn add(&mut self, time_ms: i32, samples: &[f32], volume: f32) {
+        let real_ms = (time_ms - self.start_ms) as f64 / self.clock_rate;
+        let offset = (real_ms * SAMPLE_RATE as f64 / 1000.0) as i64 * CHANNELS as i64;
+        let skip = (-offset).max(0) as usize;
+        if let Some(buffer) = self.buffer.get_mut(offset.max(0) as usize..) {
+            for (out, sample) in buffer.iter_mut().zip(samples.iter().skip(skip)) {
+                *out += sample * volume;
+            }
+        }
+    }
+
+    /// Writes the track as a 16-bit PCM WAV file.
+    pub fn write_wav(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let data_len = (self.buffer.len() * 2) as u32;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(36 + data_len).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?; // PCM
+        file.write_all(&(CHANNELS as u16).to_le_bytes())?;
+        file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        file.write_all(&(SAMPLE_RATE * CHANNELS as u32 * 2).to_le_bytes())?;
+        file.write_all(&(CHANNELS as u16 * 2).to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&data_len.to_le_bytes())?;
+        for sample in &self.buffer {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            file.write_all(&sample.to_le_bytes())?;
+        }
+        file.flush()
+    }
+}
+
+fn ms_to_frame(ms: i32) -> usize {
+    (ms.max(0) as u64 * SAMPLE_RATE as u64 / 1000) as usize
+}
+
+/// Finds hitsound samples in the beatmap folder and the skin, decoding each file once.
+/// Sounds neither of them has are synthesized, so every render has hitsounds.
+pub struct SampleLibrary {
+    beatmap_dir: PathBuf,
+    skin_dir: Option<PathBuf>,
+    cache: HashMap<PathBuf, Option<Rc<Vec<f32>>>>,
+    defaults: HashMap<String, Rc<Vec<f32>>>,
+}
+
+impl SampleLibrary {
+    pub fn new(beatmap_dir: PathBuf, skin_dir: Option<PathBuf>) -> Self {
+        Self {
+            beatmap_dir,
+            skin_dir,
+            cache: HashMap::new(),
+            defaults: HashMap::new(),
+        }
+    }
+
+    /// Looks up e.g. `soft-hitclap2`, trying the beatmap's custom samples before the skin's
+    /// and falling back to a synthesized default.
+    pub fn get(&mut self, set: &str, sound: &str, index: u32) -> Rc<Vec<f32>> {
+        let name = format!("{}-hit{}", set, sound);
+        let mut candidates = Vec::new();
+        if index == 1 {
+            candidates.push(self.beatmap_dir.join(&name));
+        } else if index > 1 {
+            candidates.push(self.beatmap_dir.join(format!("{}{}", name, index)));
+        }
+        if let Some(skin_dir) = &self.skin_dir {
+            candidates.push(skin_dir.join(&name));
+        }
+
+        match candidates.into_iter().find_map(|base| self.load(base)) {
+            Some(samples) => samples,
+            None => self
+                .defaults
+                .entry(name)
+                .or_insert_with(|| Rc::new(default_sample(set, sound)))
+                .clone(),
+        }
+    }
+
+    fn load(&mut self, base: PathBuf) -> Option<Rc<Vec<f32>>> {
+        if let Some(samples) = self.cache.get(&base) {
+            return samples.clone();
+        }
+        let samples = ["wav", "ogg", "mp3"]
+            .iter()
+            .map(|extension| base.with_extension(extension))
+            .find(|path| path.is_file())
+            .and_then(|path| match decode(&path) {
+                Ok(samples) => Some(Rc::new(samples)),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    None
+                }
+            });
+        self.cache.insert(base, samples.clone());
+        samples
+    }
+}
+
+/// Synthesizes a stand-in for a default skin sample: a short pitched knock for hitnormal,
+/// a falling tone for whistles, a long noisy crash for finishes and a noise burst for claps.
+/// Soft sounds are quieter and lower, drum sounds lower and punchier.
+fn default_sample(set: &str, sound: &str) -> Vec<f32> {
+    let (pitch, volume) = match set {
+        "soft" => (0.8, 0.6),
+        "drum" => (0.5, 1.0),
+        _ => (1.0, 0.8),
+    };
+    let (length_ms, decay_ms, tone, noise) = match sound {
+        "whistle" => (300, 120.0, 0.8, 0.0),
+        "finish" => (1000, 350.0, 0.3, 0.7),
+        "clap" => (150, 40.0, 0.0, 1.0),
+        _ => (120, 30.0, 0.7, 0.3),
+    };
+    let base_hz = match sound {
+        "whistle" => 1800.0,
+        "finish" => 500.0,
+        _ => 220.0,
+    } * pitch;
+
+    // Deterministic noise so renders don't change from run to run
+    let mut seed = 0x2545_f491_u32;
+    let mut phase = 0.0f32;
+    let frames = ms_to_frame(length_ms);
+    let mut samples = Vec::with_capacity(frames * CHANNELS);
+    for frame in 0..frames {
+        let t = frame as f32 / SAMPLE_RATE as f32;
+        // Whistles fall by a fifth over their length
+        let hz = if sound == "whistle" {
+            base_hz * (1.0 - t * 1000.0 / length_ms as f32 / 3.0)
+        } else {
+            base_hz
+        };
+        phase += std::f32::consts::TAU * hz / SAMPLE_RATE as f32;
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let white = seed as f32 / u32::MAX as f32 * 2.0 - 1.0;
+        let envelope = (-t * 1000.0 / decay_ms).exp();
+        let sample = (phase.sin() * tone + white * noise) * envelope * volume * 0.5;
+        samples.extend_from_slice(&[sample; CHANNELS]);
+    }
+    samples
+}
+
+/// A hitsound triggered at a point in time, after sample set inheritance has been resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hitsound {
+    pub time_ms: i32,
+    pub sample_set: &'static str,
+    pub addition_set: &'static str,
+    pub additions: Additions,
+    pub index: u32,
+    pub volume: f32,
+}
+
+fn sample_set_name(sample_set: SampleSet) -> Option<&'static str> {
+    match sample_set {
+        SampleSet::Normal => Some("normal"),
+        SampleSet::Soft => Some("soft"),
+        SampleSet::Drum => Some("drum"),
+        _ => None,
+    }
+}
+
+/// Resolves the sounds `object` schedules at `time_ms`, falling back to the active timing point
+/// and then the beatmap's default sample set for anything the object leaves unset.
+fn resolve_hitsound(
+    beatmap: &Beatmap,
+    object: &HitObject,
+    time_ms: i32,
+    additions: Additions,
+    sets: (SampleSet, SampleSet),
+) -> Hitsound {
+    let timing_point = beatmap
+        .timing_points
+        .iter()
+        .rev()
+        .find(|tp| tp.time.0 <= time_ms)
+        .or_else(|| beatmap.timing_points.first());
+
+    let sample_set = sample_set_name(sets.0)
+        .or_else(|| timing_point.and_then(|tp| sample_set_name(tp.sample_set)))
+        .or_else(|| sample_set_name(beatmap.sample_set))
+        .unwrap_or("normal");
+    let addition_set = sample_set_name(sets.1).unwrap_or(sample_set);
+
+    let index = if object.sample_info.custom_index != 0 {
+        object.sample_info.custom_index as u32
+    } else {
+        timing_point.map(|tp| tp.sample_index as u32).unwrap_or(0)
+    };
+    let volume = if object.sample_info.sample_volume != 0 {
+        object.sample_info.sample_volume as f32
+    } else {
+        timing_point.map(|tp| tp.volume as f32).unwrap_or(100.0)
+    };
+
+    Hitsound {
+        time_ms,
+        sample_set,
+        addition_set,
+        additions,
+        index,
+        volume: volume / 100.0,
+    }
+}
+
+/// Hitsounds `object` can play, with circles and slider heads played at `hit_ms`.
+/// Sliders return one hitsound per edge: the head, every repeat and the tail.
+pub fn object_hitsounds(beatmap: &Beatmap, object: &HitObject, hit_ms: i32) -> Vec<Hitsound> {
+    let object_sets = (
+        object.sample_info.sample_set,
+        object.sample_info.addition_set,
+    );
+    match &object.kind {
+        HitObjectKind::Circle => vec![Hitsound {
+            time_ms: hit_ms,
+            ..resolve_hitsound(
+                beatmap,
+                object,
+                object.start_time.0,
+                object.additions,
+                object_sets,
+            )
+        }],
+        HitObjectKind::Slider(info) => {
+            // Same edges the slider is judged on, so sounds line up with the judged events
+            let timing = SliderTiming::new(beatmap, object, info);
+            let edge_times = std::iter::once(timing.start_ms)
+                .chain(timing.repeat_times().into_iter().map(|time| time as i32))
+                .chain(std::iter::once(timing.end_ms()));
+            edge_times
+                .enumerate()
+                .map(|(edge, time_ms)| {
+                    let additions = info
+                        .edge_hitsounds
+                        .get(edge)
+                        .copied()
+                        .unwrap_or(object.additions);
+                    let sets = info
+                        .edge_additions
+                        .get(edge)
+                        .copied()
+                        .unwrap_or(object_sets);
+                    let hitsound = resolve_hitsound(beatmap, object, time_ms, additions, sets);
+                    if edge == 0 {
+                        Hitsound {
+                            time_ms: hit_ms,
+                            ..hitsound
+                        }
+                    } else {
+                        hitsound
+                    }
+                })
+                .collect()
+        }
+        HitObjectKind::Spinner(SpinnerInfo { end_time }) => vec![resolve_hitsound(
+            beatmap,
+            object,
+            end_time.0,
+            object.additions,
+            object_sets,
+        )],
+    }
+}
+
+/// Mixes the hitsounds of every judged hit into a track covering `start_ms..end_ms`.
+/// Sounds play at the replay's hit times, and slider edges only when they were caught.
+pub fn mix_hitsounds(
+    map_data: &BeatmapData,
+    samples: &mut SampleLibrary,
+    judgements: &[ObjectJudgement],
+    start_ms: i32,
+    end_ms: i32,
+) -> Mixer {
+    let mut mixer = Mixer::new(start_ms, end_ms, map_data.clock_rate);
+    for (object, judgement) in map_data.beatmap.hit_objects.iter().zip(judgements) {
+        let hit_ms = judgement
+            .hit_offset
+            .map(|offset| object.start_time.0 + offset);
+        let edges_hit: Vec<_> = judgement
+            .slider_events
+            .iter()
+            .filter(|event| event.kind != SliderEventKind::Tick)
+            .map(|event| event.hit)
+            .collect();
+        let hitsounds = object_hitsounds(
+            &map_data.beatmap,
+            object,
+            hit_ms.unwrap_or(object.start_time.0),
+        );
+        for (edge, hitsound) in hitsounds.into_iter().enumerate() {
+            let hit = match object.kind {
+                HitObjectKind::Slider(..) => edges_hit.get(edge).copied().unwrap_or(false),
+                // Spinners play when they end, unless they were missed
+                HitObjectKind::Spinner(..) => judgement.result != HitResult::Miss,
+                _ => hit_ms.is_some(),
+            };
+            if !hit {
+                continue;
+            }
+
+            let mut sounds = vec![(hitsound.sample_set, "normal")];
+            for &(addition, name) in &[
+                (Additions::WHISTLE, "whistle"),
+                (Additions::FINISH, "finish"),
+                (Additions::CLAP, "clap"),
+            ] {
+                if hitsound.additions.contains(addition) {
+                    sounds.push((hitsound.addition_set, name));
+                }
+            }
+            for (set, sound) in sounds {
+                let data = samples.get(set, sound, hitsound.index);
+                mixer.add(hitsound.time_ms, &data, hitsound.volume);
+            }
+        }
+    }
+    mixer
+}
+
+#[test]
+fn test_mixer_add() {
+    let mut mixer = Mixer::new(1000, 2000, 1.0);
+    assert_eq!(mixer.buffer.len(), SAMPLE_RATE as usize * CHANNELS);
+
+    mixer.add(1000, &[1.0, 1.0, 0.5, 0.5], 0.5);
+    assert_eq!(&mixer.buffer[..4], &[0.5, 0.5, 0.25, 0.25]);
+
+    // Starts 50ms before the track, only the last 50ms are audible
+    let mut mixer = Mixer::new(1000, 2000, 1.0);
+    mixer.add(950, &vec![1.0; ms_to_frame(100) * CHANNELS], 1.0);
+    let audible = ms_to_frame(50) * CHANNELS;
+    assert!(mixer.buffer[..audible].iter().all(|&s| s == 1.0));
+    assert!(mixer.buffer[audible..].iter().all(|&s| s == 0.0));
+
+    // Past the end of the track
+    mixer.add(3000, &[1.0, 1.0], 1.0);
+    assert_eq!(mixer.buffer.len(), SAMPLE_RATE as usize * CHANNELS);
+}
+
+#[test]
+fn test_mixer_clock_rate() {
+    // 1.5x speed plays the same second of beatmap in two thirds of the time
+    let mut mixer = Mixer::new(0, 1500, 1.5);
+    assert_eq!(mixer.buffer.len(), SAMPLE_RATE as usize * CHANNELS);
+
+    mixer.add(750, &[1.0, 1.0], 1.0);
+    let offset = ms_to_frame(500) * CHANNELS;
+    assert_eq!(&mixer.buffer[offset..offset + 2], &[1.0, 1.0]);
+    assert!(mixer.buffer[..offset].iter().all(|&s| s == 0.0));
+}
+
+#[test]
+fn test_default_samples() {
+    let mut samples = SampleLibrary::new(PathBuf::from("/nonexistent"), None);
+    for set in &["normal", "soft", "drum"] {
+        for sound in &["normal", "whistle", "finish", "clap"] {
+            let data = samples.get(set, sound, 1);
+            assert!(!data.is_empty());
+            assert!(data.iter().any(|&s| s != 0.0));
+            assert!(data.iter().all(|s| s.abs() <= 1.0));
+        }
+    }
+    // Synthesized once and shared afterwards
+    assert!(Rc::ptr_eq(
+        &samples.get("soft", "clap", 1),
+        &samples.get("soft", "clap", 2)
+    ));
+}
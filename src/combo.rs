@@ -0,0 +1,87 @@
+use libosu::prelude::*;
+
+/// Where a hit object sits in the beatmap's combos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComboInfo {
+    /// Combo colour to draw the object with, which wraps around however many colours there are
+    pub color_index: usize,
+    /// Number drawn on the object, counting up from 1 in each combo
+    pub number: u32,
+}
+
+impl ComboInfo {
+    /// Picks this combo's colour out of `colors`, which can't be empty.
+    pub fn color<T: Copy>(&self, colors: &[T]) -> T {
+        colors[self.color_index % colors.len()]
+    }
+}
+
+/// Combo colour and number of every hit object, in beatmap order.
+/// New combos move on to the next colour, skipping as many more as the object asks for,
+/// and the object after a spinner always starts a new combo.
+pub fn combo_infos(objects: &[HitObject]) -> Vec<ComboInfo> {
+    let mut infos: Vec<ComboInfo> = Vec::with_capacity(objects.len());
+    let mut prev_spinner = false;
+    for object in objects {
+        let skip = object.skip_color.max(0) as usize;
+        let info = match infos.last() {
+            None => ComboInfo {
+                color_index: skip,
+                number: 1,
+            },
+            Some(prev) if object.new_combo || prev_spinner => ComboInfo {
+                color_index: prev.color_index + 1 + skip,
+                number: 1,
+            },
+            Some(prev) => ComboInfo {
+                number: prev.number + 1,
+                ..*prev
+            },
+        };
+        infos.push(info);
+        prev_spinner = matches!(object.kind, HitObjectKind::Spinner(..));
+    }
+    infos
+}
+
+#[test]
+fn test_combo_infos() {
+    let object = |new_combo: bool, skip_color: i32, kind: HitObjectKind| HitObject {
+        pos: Point::new(0, 0),
+        start_time: TimestampMillis(0),
+        kind,
+        new_combo,
+        skip_color,
+        additions: Additions::empty(),
+        sample_info: SampleInfo::default(),
+    };
+    let spinner = || {
+        HitObjectKind::Spinner(SpinnerInfo {
+            end_time: TimestampMillis(0),
+        })
+    };
+    let objects = vec![
+        object(true, 0, HitObjectKind::Circle),
+        object(false, 0, HitObjectKind::Circle),
+        object(false, 0, HitObjectKind::Circle),
+        object(true, 2, HitObjectKind::Circle),
+        object(false, 0, HitObjectKind::Circle),
+        object(true, 0, spinner()),
+        object(false, 0, HitObjectKind::Circle),
+    ];
+    let infos: Vec<_> = combo_infos(&objects)
+        .iter()
+        .map(|info| (info.color_index, info.number))
+        .collect();
+    assert_eq!(
+        infos,
+        vec![(0, 1), (0, 2), (0, 3), (3, 1), (3, 2), (4, 1), (5, 1)]
+    );
+
+    let colors = ["red", "green"];
+    let info = ComboInfo {
+        color_index: 3,
+        number: 1,
+    };
+    assert_eq!(info.color(&colors), "green");
+}
@@ -1,62 +1,258 @@
 use std::{
-    io::Write,
+    io::{self, Write},
     path::{Path, PathBuf},
     process::{Child, ChildStdin, Command, Stdio},
 };
 
+use crate::audio::SAMPLE_RATE;
+
+/// Audio file muxed into the output video, placed on the video's timeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioTrack {
+    pub path: PathBuf,
+    /// Position in the audio file the video starts at, in milliseconds
+    pub seek_ms: i32,
+    /// Silence inserted before the audio starts, in milliseconds
+    pub delay_ms: i32,
+    /// Length of audio to read after seeking, in milliseconds
+    pub duration_ms: i32,
+    pub volume: f32,
+    /// Playback speed, with the times above in the audio's own (unscaled) time
+    pub clock_rate: f64,
+    /// Whether changing speed changes the pitch too, like Nightcore, instead of time stretching
+    pub changes_pitch: bool,
+}
+
+impl AudioTrack {
+    /// Places audio whose time 0 is beatmap time 0 onto a video covering `start_ms..end_ms`.
+    /// Negative start times (the lead-in before the song) become leading silence.
+    pub fn new(path: PathBuf, start_ms: i32, end_ms: i32, volume: f32) -> Self {
+        let seek_ms = start_ms.max(0);
+        let delay_ms = (-start_ms).max(0);
+        Self {
+            path,
+            seek_ms,
+            delay_ms,
+            duration_ms: (end_ms - start_ms - delay_ms).max(0),
+            volume,
+            clock_rate: 1.0,
+            changes_pitch: false,
+        }
+    }
+
+    /// Plays the audio `clock_rate` times faster.
+    pub fn with_clock_rate(mut self, clock_rate: f64, changes_pitch: bool) -> Self {
+        self.clock_rate = clock_rate;
+        self.changes_pitch = changes_pitch;
+        self
+    }
+
+    /// ffmpeg filters changing the audio's speed, empty at normal speed.
+    fn speed_filter(&self) -> String {
+        if self.clock_rate == 1.0 {
+            String::new()
+        } else if self.changes_pitch {
+            // Pretend the audio was recorded at a different sample rate, then convert back
+            format!(
+                "aresample={rate},asetrate={sped_up},aresample={rate},",
+                rate = SAMPLE_RATE,
+                sped_up = (SAMPLE_RATE as f64 * self.clock_rate).round()
+            )
+        } else {
+            format!("atempo={},", self.clock_rate)
+        }
+    }
+}
+
 pub struct Encoder {
     child: Child,
     child_in: ChildStdin,
+    output: PathBuf,
     pub width: u16,
     pub height: u16,
     pub framerate: u16,
 }
 
 impl Encoder {
-    pub fn new(width: u16, height: u16, framerate: u16) -> Self {
-        let mut child = Command::new("ffmpeg")
-            .args(&[
-                "-vcodec",
-                "rawvideo",
-                "-f",
-                "rawvideo",
-                "-pix_fmt",
-                "rgba",
-                "-s",
-                &format!("{}x{}", width, height),
-                "-framerate",
-                &framerate.to_string(),
-                "-i",
-                "pipe:0",
-                "-vcodec",
-                "libx264",
-                "-r",
-                &framerate.to_string(),
-                "./out.mp4",
-                "-y",
-            ])
+    pub fn new(
+        output: &Path,
+        width: u16,
+        height: u16,
+        framerate: u16,
+        audio: &[AudioTrack],
+    ) -> io::Result<Self> {
+        let mut command = Command::new("ffmpeg");
+        command.args([
+            "-vcodec",
+            "rawvideo",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgba",
+            "-s",
+            &format!("{}x{}", width, height),
+            "-framerate",
+            &framerate.to_string(),
+            "-i",
+            "pipe:0",
+        ]);
+        for track in audio {
+            command
+                .args([
+                    "-ss",
+                    &format_seconds(track.seek_ms),
+                    "-t",
+                    &format_seconds(track.duration_ms),
+                    "-i",
+                ])
+                .arg(&track.path);
+        }
+        if !audio.is_empty() {
+            command.args([
+                "-filter_complex",
+                &audio_filter(audio),
+                "-map",
+                "0:v",
+                "-map",
+                "[audio]",
+                "-acodec",
+                "aac",
+            ]);
+        }
+        let mut child = command
+            .args(["-vcodec", "libx264", "-r", &framerate.to_string()])
+            .arg(output)
+            .arg("-y")
             .stdin(Stdio::piped())
             .stdout(Stdio::null())
             .spawn()
-            .expect("Couldn't spawn ffmpeg");
-        Self {
-            child_in: child.stdin.take().expect("Couldn't write to ffmpeg"),
+            .map_err(|e| io::Error::new(e.kind(), format!("Couldn't run ffmpeg: {}", e)))?;
+        let child_in = child
+            .stdin
+            .take()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "Couldn't write to ffmpeg"))?;
+        Ok(Self {
+            child_in,
             child,
+            output: output.to_path_buf(),
             width,
             height,
             framerate,
-        }
+        })
     }
 
-    pub fn encode(&mut self, data: &[u8]) {
+    pub fn encode(&mut self, data: &[u8]) -> io::Result<()> {
         assert_eq!(data.len(), self.width as usize * self.height as usize * 4);
-        self.child_in.write_all(&data).unwrap();
+        // ffmpeg quits early when it can't read one of the inputs, like a broken song file
+        self.child_in.write_all(data).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("ffmpeg stopped accepting frames, check its output: {}", e),
+            )
+        })
     }
 
-    pub fn finish(mut self) -> PathBuf {
-        self.child_in.flush().unwrap();
+    /// Waits for ffmpeg to finish writing the video, returning where it was written.
+    pub fn finish(mut self) -> io::Result<PathBuf> {
+        self.child_in.flush()?;
         std::mem::drop(self.child_in);
-        self.child.wait().unwrap();
-        Path::new("./out.mp4").to_path_buf()
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "ffmpeg failed to encode the video ({})",
+                status
+            )));
+        }
+        Ok(self.output)
+    }
+}
+
+/// ffmpeg filter graph placing every audio input on the video's timeline and mixing them into `[audio]`.
+/// Audio inputs follow the video input, so track `i` is input `i + 1`.
+fn audio_filter(audio: &[AudioTrack]) -> String {
+    let mut filter = String::new();
+    for (i, track) in audio.iter().enumerate() {
+        filter += &format!(
+            "[{input}:a]{speed}volume={volume},adelay={delay}|{delay}[a{i}];",
+            input = i + 1,
+            speed = track.speed_filter(),
+            volume = track.volume,
+            // The delay is silence on the video's timeline, which runs in real time
+            delay = (track.delay_ms as f64 / track.clock_rate) as i32,
+            i = i
+        );
+    }
+    for i in 0..audio.len() {
+        filter += &format!("[a{}]", i);
     }
+    // amix divides every input's volume by the input count, undo that so tracks keep their volume
+    filter += &format!(
+        "amix=inputs={n}:duration=longest:dropout_transition=0,volume={n}[audio]",
+        n = audio.len()
+    );
+    filter
+}
+
+fn format_seconds(ms: i32) -> String {
+    format!("{}.{:03}", ms / 1000, ms % 1000)
+}
+
+#[test]
+fn test_audio_track_timeline() {
+    let track = AudioTrack::new(PathBuf::from("audio.mp3"), 0, 10_000, 1.0);
+    assert_eq!(
+        (track.seek_ms, track.delay_ms, track.duration_ms),
+        (0, 0, 10_000)
+    );
+
+    let track = AudioTrack::new(PathBuf::from("audio.mp3"), 5_000, 10_000, 1.0);
+    assert_eq!(
+        (track.seek_ms, track.delay_ms, track.duration_ms),
+        (5_000, 0, 5_000)
+    );
+
+    // Lead-in before the song starts
+    let track = AudioTrack::new(PathBuf::from("audio.mp3"), -1_500, 10_000, 1.0);
+    assert_eq!(
+        (track.seek_ms, track.delay_ms, track.duration_ms),
+        (0, 1_500, 10_000)
+    );
+}
+
+#[test]
+fn test_format_seconds() {
+    assert_eq!(format_seconds(0), "0.000");
+    assert_eq!(format_seconds(1_500), "1.500");
+    assert_eq!(format_seconds(61_042), "61.042");
+}
+
+#[test]
+fn test_audio_filter() {
+    let song = AudioTrack::new(PathBuf::from("audio.mp3"), -500, 1000, 0.8);
+    let hitsounds = AudioTrack::new(PathBuf::from("hitsounds.wav"), 0, 1500, 1.0);
+    assert_eq!(
+        audio_filter(std::slice::from_ref(&song)),
+        "[1:a]volume=0.8,adelay=500|500[a0];\
+         [a0]amix=inputs=1:duration=longest:dropout_transition=0,volume=1[audio]"
+    );
+    assert_eq!(
+        audio_filter(&[song, hitsounds]),
+        "[1:a]volume=0.8,adelay=500|500[a0];[2:a]volume=1,adelay=0|0[a1];\
+         [a0][a1]amix=inputs=2:duration=longest:dropout_transition=0,volume=2[audio]"
+    );
+}
+
+#[test]
+fn test_audio_filter_clock_rate() {
+    let song = AudioTrack::new(PathBuf::from("audio.mp3"), -1500, 1000, 1.0);
+    assert_eq!(
+        audio_filter(&[song.clone().with_clock_rate(1.5, false)]),
+        "[1:a]atempo=1.5,volume=1,adelay=1000|1000[a0];\
+         [a0]amix=inputs=1:duration=longest:dropout_transition=0,volume=1[audio]"
+    );
+    assert_eq!(
+        audio_filter(&[song.with_clock_rate(1.5, true)]),
+        "[1:a]aresample=44100,asetrate=66150,aresample=44100,volume=1,adelay=1000|1000[a0];\
+         [a0]amix=inputs=1:duration=longest:dropout_transition=0,volume=1[audio]"
+    );
 }
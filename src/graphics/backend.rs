@@ -0,0 +1,146 @@
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use ggez::{
+    graphics::{Color, Rect},
+    GameError, GameResult,
+};
+use glam::Vec2;
+use image::RgbaImage;
+
+/// CPU-side image that every backend can draw.
+/// GPU backends upload it lazily the first time it is drawn.
+pub struct Texture {
+    image: RgbaImage,
+    pub(super) gpu_image: RefCell<Option<ggez::graphics::Image>>,
+}
+
+impl Texture {
+    pub fn new(image: RgbaImage) -> Rc<Self> {
+        Rc::new(Self {
+            image,
+            gpu_image: RefCell::new(None),
+        })
+    }
+
+    pub fn from_path(path: &Path) -> GameResult<Rc<Self>> {
+        let image = image::open(path).map_err(|e| {
+            GameError::ResourceLoadError(format!("Couldn't load {}: {}", path.display(), e))
+        })?;
+        Ok(Self::new(image.to_rgba8()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> GameResult<Rc<Self>> {
+        let image = image::load_from_memory(bytes)
+            .map_err(|e| GameError::ResourceLoadError(format!("Couldn't decode image: {}", e)))?;
+        Ok(Self::new(image.to_rgba8()))
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    pub fn width(&self) -> f32 {
+        self.image.width() as f32
+    }
+
+    pub fn height(&self) -> f32 {
+        self.image.height() as f32
+    }
+
+    pub fn size(&self) -> Vec2 {
+        glam::vec2(self.width(), self.height())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DrawMode {
+    Fill,
+    Stroke(f32),
+}
+
+/// Placement of a sprite, mirroring ggez's `DrawParam`.
+/// `offset` is the normalized origin inside the texture that `dest` refers to.
+/// `src` is the normalized part of the texture to draw, which `offset` is relative to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteParams {
+    pub src: Rect,
+    pub dest: Vec2,
+    pub scale: Vec2,
+    pub offset: Vec2,
+    pub rotation: f32,
+    pub color: Color,
+}
+
+impl Default for SpriteParams {
+    fn default() -> Self {
+        Self {
+            src: Rect::one(),
+            dest: glam::vec2(0.0, 0.0),
+            scale: glam::vec2(1.0, 1.0),
+            offset: glam::vec2(0.0, 0.0),
+            rotation: 0.0,
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl SpriteParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn src(mut self, src: Rect) -> Self {
+        self.src = src;
+        self
+    }
+
+    pub fn dest(mut self, dest: Vec2) -> Self {
+        self.dest = dest;
+        self
+    }
+
+    pub fn scale(mut self, scale: Vec2) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+/// Render target that the graphics module draws into.
+pub trait Backend {
+    /// Size of the render target in pixels.
+    fn size(&self) -> Vec2;
+
+    fn clear(&mut self, color: Color);
+
+    fn draw_sprite(&mut self, texture: &Texture, params: SpriteParams) -> GameResult;
+
+    fn draw_circle(
+        &mut self,
+        mode: DrawMode,
+        center: Vec2,
+        radius: f32,
+        color: Color,
+    ) -> GameResult;
+
+    fn draw_polyline(&mut self, mode: DrawMode, points: &[Vec2], color: Color) -> GameResult;
+
+    fn draw_rect(&mut self, mode: DrawMode, pos: Vec2, size: Vec2, color: Color) -> GameResult;
+
+    /// Draws `text` with its top-left corner at `pos`, `size` pixels tall.
+    fn draw_text(&mut self, text: &str, pos: Vec2, size: f32, color: Color) -> GameResult;
+}
@@ -1,91 +1,201 @@
-use ggez::graphics::{Color, DrawParam, Drawable, Text};
-use glam::vec2;
-use libosu::prelude::HitObject;
+use ggez::{graphics::Color, GameResult};
+use glam::{vec2, Vec2};
+use libosu::prelude::{HitObject, Mods};
 
-use crate::BeatmapData;
+use super::{
+    backend::{Backend, SpriteParams},
+    fade::{circle_opacity, faded},
+    playfield::Playfield,
+};
+use crate::{helper::od_to_hit_windows, skin::Skin, BeatmapData};
 
+/// Radius of a hit circle in skin images, which other circle parts are sized relative to.
+pub const LEGACY_CIRCLE_RADIUS: f32 = 64.0;
+
+/// How long a hit circle takes to grow and fade out after being hit, in milliseconds.
+const HIT_FADE_OUT_MS: f32 = 240.0;
+
+/// Size a hit circle grows to by the time it has faded out.
+const HIT_SCALE: f32 = 1.4;
+
+/// Time at which a circle is gone: once its hit animation is over, or once it can't be hit anymore.
+pub fn circle_end_ms(start_ms: i32, hit_ms: Option<i32>, window_50: i32) -> i32 {
+    match hit_ms {
+        Some(hit_ms) => hit_ms + HIT_FADE_OUT_MS as i32,
+        None => start_ms + window_50,
+    }
+}
+
+/// Scale and opacity of a circle at `current_ms` on top of its fade in.
+/// Hit circles grow as they fade out, missed ones fade out in place until they can't be hit anymore.
+fn hit_fade(current_ms: i32, start_ms: i32, hit_ms: Option<i32>, window_50: i32) -> (f32, f32) {
+    match hit_ms {
+        Some(hit_ms) if current_ms >= hit_ms => {
+            let t = ((current_ms - hit_ms) as f32 / HIT_FADE_OUT_MS).min(1.0);
+            // Eases out, growing quickly at first
+            (1.0 + (HIT_SCALE - 1.0) * t * (2.0 - t), 1.0 - t)
+        }
+        Some(_) => (1.0, 1.0),
+        None => {
+            let t = ((current_ms - start_ms) as f32 / window_50.max(1) as f32).clamp(0.0, 1.0);
+            (1.0, 1.0 - t)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn draw_circle(
-    ctx: &mut ggez::Context,
+    backend: &mut dyn Backend,
+    playfield: &Playfield,
     map_data: &BeatmapData,
+    skin: &Skin,
     current_ms: i32,
     object: &HitObject,
     combo_color: Color,
-    combo_index: u8,
-) {
-    let hitcircle =
-        ggez::graphics::Image::new(ctx, "/Skins/Varvalian 2019-06-25/hitcircle.png").unwrap();
-    hitcircle
-        .draw(
-            ctx,
-            DrawParam::new()
-                .dest(vec2(object.pos.x as f32, object.pos.y as f32))
-                .offset(vec2(0.5, 0.5))
-                .scale(
-                    vec2(map_data.cs_osupixels * 2.0, map_data.cs_osupixels * 2.0)
-                        / vec2(hitcircle.dimensions().w, hitcircle.dimensions().h),
-                ),
-        )
-        .unwrap();
-
-    let hitcircleoverlay =
-        ggez::graphics::Image::new(ctx, "/Skins/Varvalian 2019-06-25/hitcircleoverlay.png")
-            .unwrap();
-    hitcircleoverlay
-        .draw(
-            ctx,
-            DrawParam::new()
-                .dest(vec2(object.pos.x as f32, object.pos.y as f32))
-                .offset(vec2(0.5, 0.5))
-                .scale(
-                    vec2(map_data.cs_osupixels * 2.0, map_data.cs_osupixels * 2.0)
-                        / vec2(
-                            hitcircleoverlay.dimensions().w,
-                            hitcircleoverlay.dimensions().h,
-                        ),
-                )
-                .color(combo_color),
-        )
-        .unwrap();
-
-    let approach_circle_size = (object.start_time.0 - current_ms) as f32 / map_data.ar_ms as f32;
-
-    let radius = map_data.cs_osupixels * (1.0 + approach_circle_size);
-
-    let approachcircle =
-        ggez::graphics::Image::new(ctx, "/Skins/Varvalian 2019-06-25/approachcircle.png").unwrap();
-    approachcircle
-        .draw(
-            ctx,
-            DrawParam::new()
-                .dest(vec2(object.pos.x as f32, object.pos.y as f32))
-                .offset(vec2(0.5, 0.5))
-                .scale(
-                    vec2(radius * 2.0, radius * 2.0)
-                        / vec2(approachcircle.dimensions().w, approachcircle.dimensions().h),
-                )
-                .color(combo_color),
-        )
-        .unwrap();
-
-    let combo_number = if combo_index < 10 {
-        ggez::graphics::Image::new(
-            ctx,
-            format!("/Skins/Varvalian 2019-06-25/combo-{}.png", combo_index),
-        )
-        .unwrap()
-    } else {
-        ggez::graphics::Image::new(ctx, "/Skins/Varvalian 2019-06-25/ranking-B.png").unwrap()
-    };
-    combo_number
-        .draw(
-            ctx,
-            DrawParam::new()
-                .dest(vec2(object.pos.x as f32, object.pos.y as f32))
+    combo_index: u32,
+    hit_ms: Option<i32>,
+) -> GameResult {
+    let pos = playfield.to_screen(vec2(object.pos.x as f32, object.pos.y as f32));
+    let circle_radius = map_data.cs_osupixels * playfield.scale();
+    let hidden = map_data.mods.contains(Mods::Hidden);
+    let window_50 = od_to_hit_windows(map_data.difficulty.overall_difficulty).2 as i32;
+    let (scale, fade_out) = hit_fade(current_ms, object.start_time.0, hit_ms, window_50);
+    let opacity =
+        circle_opacity(current_ms, object.start_time.0, map_data.ar_ms, hidden) * fade_out;
+    let size = vec2(circle_radius * 2.0, circle_radius * 2.0) * scale;
+    let white = Color::new(1.0, 1.0, 1.0, 1.0);
+
+    let hitcircle = &skin.element("hitcircle").texture;
+    backend.draw_sprite(
+        hitcircle,
+        SpriteParams::new()
+            .dest(pos)
+            .offset(vec2(0.5, 0.5))
+            .scale(size / hitcircle.size())
+            .color(faded(white, opacity)),
+    )?;
+
+    let hitcircleoverlay = &skin.element("hitcircleoverlay").texture;
+    backend.draw_sprite(
+        hitcircleoverlay,
+        SpriteParams::new()
+            .dest(pos)
+            .offset(vec2(0.5, 0.5))
+            .scale(size / hitcircleoverlay.size())
+            .color(faded(combo_color, opacity)),
+    )?;
+
+    // Hidden only keeps the first object's approach circle, as a hint for when the map starts
+    let is_first_object = map_data
+        .beatmap
+        .hit_objects
+        .first()
+        .map(|first| first.start_time)
+        == Some(object.start_time);
+    let is_hit = hit_ms.map(|hit_ms| current_ms >= hit_ms).unwrap_or(false);
+    if current_ms < object.start_time.0 && !is_hit && (!hidden || is_first_object) {
+        let approach_circle_size =
+            (object.start_time.0 - current_ms) as f32 / map_data.ar_ms as f32;
+
+        let radius = circle_radius * (1.0 + approach_circle_size);
+
+        let approachcircle = &skin.element("approachcircle").texture;
+        backend.draw_sprite(
+            approachcircle,
+            SpriteParams::new()
+                .dest(pos)
                 .offset(vec2(0.5, 0.5))
-                .scale(
-                    vec2(map_data.cs_osupixels, map_data.cs_osupixels)
-                        / vec2(combo_number.dimensions().w, combo_number.dimensions().h),
-                ),
-        )
-        .unwrap();
+                .scale(vec2(radius * 2.0, radius * 2.0) / approachcircle.size())
+                .color(faded(
+                    combo_color,
+                    circle_opacity(current_ms, object.start_time.0, map_data.ar_ms, false),
+                )),
+        )?;
+    }
+
+    // The number disappears as soon as the circle is hit
+    if is_hit {
+        return Ok(());
+    }
+    draw_combo_number(
+        backend,
+        skin,
+        combo_index,
+        pos,
+        circle_radius / LEGACY_CIRCLE_RADIUS,
+        faded(white, opacity),
+    )
+}
+
+/// Left edge of each digit relative to the middle of the number,
+/// with neighbouring digits overlapping by `overlap` (a gap when negative).
+fn digit_offsets(widths: &[f32], overlap: f32) -> Vec<f32> {
+    let total = widths.iter().sum::<f32>() - overlap * widths.len().saturating_sub(1) as f32;
+    let mut x = -total / 2.0;
+    widths
+        .iter()
+        .map(|width| {
+            let left = x;
+            x += width - overlap;
+            left
+        })
+        .collect()
+}
+
+/// Draws `number` centred on `pos` from the skin's hit circle digits, `scale` times their size in the skin.
+fn draw_combo_number(
+    backend: &mut dyn Backend,
+    skin: &Skin,
+    number: u32,
+    pos: Vec2,
+    scale: f32,
+    color: Color,
+) -> GameResult {
+    let digits: Vec<_> = number
+        .to_string()
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|digit| skin.numbered_element(&skin.ini.hit_circle_prefix, digit))
+        .collect();
+    let widths: Vec<_> = digits.iter().map(|digit| digit.size().x).collect();
+    let offsets = digit_offsets(&widths, skin.ini.hit_circle_overlap as f32);
+    for (digit, left) in digits.iter().zip(offsets) {
+        backend.draw_sprite(
+            &digit.texture,
+            SpriteParams::new()
+                .dest(pos + vec2(left * scale, 0.0))
+                .offset(vec2(0.0, 0.5))
+                .scale(digit.size() * scale / digit.texture.size())
+                .color(color),
+        )?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_hit_fade() {
+    // Hit late at 1050
+    assert_eq!(hit_fade(1020, 1000, Some(1050), 150), (1.0, 1.0));
+    assert_eq!(hit_fade(1050, 1000, Some(1050), 150), (1.0, 1.0));
+    assert_eq!(hit_fade(1170, 1000, Some(1050), 150), (1.3, 0.5));
+    assert_eq!(hit_fade(1290, 1000, Some(1050), 150), (HIT_SCALE, 0.0));
+    assert_eq!(circle_end_ms(1000, Some(1050), 150), 1290);
+
+    // Missed
+    assert_eq!(hit_fade(900, 1000, None, 150), (1.0, 1.0));
+    assert_eq!(hit_fade(1075, 1000, None, 150), (1.0, 0.5));
+    assert_eq!(circle_end_ms(1000, None, 150), 1150);
+}
+
+#[test]
+fn test_digit_offsets() {
+    assert_eq!(digit_offsets(&[20.0], -2.0), vec![-10.0]);
+    // 20 + 2 + 10 wide
+    assert_eq!(digit_offsets(&[20.0, 10.0], -2.0), vec![-16.0, 6.0]);
+    // Overlapping by 4 leaves 3 * 20 - 2 * 4 = 52 wide
+    assert_eq!(
+        digit_offsets(&[20.0, 20.0, 20.0], 4.0),
+        vec![-26.0, -10.0, 6.0]
+    );
+    assert!(digit_offsets(&[], 0.0).is_empty());
 }
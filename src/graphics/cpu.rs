@@ -0,0 +1,500 @@
+use font8x8::UnicodeFonts;
+use ggez::{graphics::Color, GameResult};
+use glam::Vec2;
+
+use super::backend::{Backend, DrawMode, SpriteParams, Texture};
+
+/// Pure software backend rendering into an RGBA8 buffer, for machines without a GPU.
+pub struct CpuBackend {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// Pixel-space bounding box, clamped to the render target.
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl Bounds {
+    fn width(&self) -> usize {
+        (self.x1 - self.x0) as usize
+    }
+}
+
+impl CpuBackend {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width: width as u32,
+            height: height as u32,
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// RGBA8 pixels of the frame, in the layout `Encoder::encode` expects.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Turns the rendered frame into an image, for generating textures.
+    pub fn into_image(self) -> image::RgbaImage {
+        image::RgbaImage::from_raw(self.width, self.height, self.data)
+            .expect("Buffer size always matches the dimensions")
+    }
+
+    fn bounds(&self, min: Vec2, max: Vec2) -> Option<Bounds> {
+        let x0 = min.x.floor().max(0.0) as u32;
+        let y0 = min.y.floor().max(0.0) as u32;
+        let x1 = (max.x.ceil().max(0.0) as u32).min(self.width);
+        let y1 = (max.y.ceil().max(0.0) as u32).min(self.height);
+        if x0 >= x1 || y0 >= y1 {
+            None
+        } else {
+            Some(Bounds { x0, y0, x1, y1 })
+        }
+    }
+
+    /// Alpha-blends `color` over the pixel at `(x, y)` with the given coverage.
+    fn blend(&mut self, x: u32, y: u32, color: Color, coverage: f32) {
+        let alpha = color.a * coverage;
+        if alpha <= 0.0 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let pixel = &mut self.data[i..i + 4];
+        let dst_a = pixel[3] as f32 / 255.0;
+        let out_a = alpha + dst_a * (1.0 - alpha);
+        // Colours are stored unpremultiplied, so translucent targets keep their colour at the edges
+        for (channel, src) in pixel.iter_mut().zip(&[color.r, color.g, color.b]) {
+            let dst = *channel as f32 / 255.0;
+            *channel = ((src * alpha + dst * dst_a * (1.0 - alpha)) / out_a * 255.0).round() as u8;
+        }
+        pixel[3] = (out_a * 255.0).round() as u8;
+    }
+
+    /// Blends `color` over every pixel of `bounds`, weighted by `coverage(pixel_center)`.
+    fn fill_with(&mut self, bounds: Bounds, color: Color, coverage: impl Fn(Vec2) -> f32) {
+        for y in bounds.y0..bounds.y1 {
+            for x in bounds.x0..bounds.x1 {
+                let c = coverage(glam::vec2(x as f32 + 0.5, y as f32 + 0.5));
+                self.blend(x, y, color, c.min(1.0));
+            }
+        }
+    }
+
+    /// Like `fill_with`, but takes a precomputed coverage mask covering `bounds`.
+    fn fill_mask(&mut self, bounds: Bounds, color: Color, mask: &[f32]) {
+        let width = bounds.width();
+        for y in bounds.y0..bounds.y1 {
+            for x in bounds.x0..bounds.x1 {
+                let c = mask[(y - bounds.y0) as usize * width + (x - bounds.x0) as usize];
+                self.blend(x, y, color, c.min(1.0));
+            }
+        }
+    }
+
+    fn points_bounds(&self, points: &[Vec2], padding: f32) -> Option<Bounds> {
+        let min = points
+            .iter()
+            .fold(glam::vec2(f32::MAX, f32::MAX), |a, &b| a.min(b));
+        let max = points
+            .iter()
+            .fold(glam::vec2(f32::MIN, f32::MIN), |a, &b| a.max(b));
+        self.bounds(min - Vec2::splat(padding), max + Vec2::splat(padding))
+    }
+
+    /// Even-odd scanline fill of the polygon described by `points`.
+    fn fill_polygon(&mut self, points: &[Vec2], color: Color) {
+        let bounds = match self.points_bounds(points, 0.0) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let mut crossings = Vec::new();
+        for y in bounds.y0..bounds.y1 {
+            let scan_y = y as f32 + 0.5;
+            crossings.clear();
+            for (i, &a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                if (a.y <= scan_y) != (b.y <= scan_y) {
+                    let x = a.x + (scan_y - a.y) / (b.y - a.y) * (b.x - a.x);
+                    // Degenerate points from callers can't be placed on the scanline
+                    if x.is_finite() {
+                        crossings.push(x);
+                    }
+                }
+            }
+            crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for span in crossings.chunks_exact(2) {
+                let start = (span[0] - 0.5).ceil().max(bounds.x0 as f32) as u32;
+                let end = ((span[1] - 0.5).floor() + 1.0)
+                    .min(bounds.x1 as f32)
+                    .max(0.0) as u32;
+                for x in start..end {
+                    self.blend(x, y, color, 1.0);
+                }
+            }
+        }
+    }
+
+    /// Anti-aliased stroke along `points`, each pixel blended once even where segments overlap.
+    fn stroke_polyline(&mut self, points: &[Vec2], width: f32, color: Color) {
+        let half_width = width / 2.0;
+        let bounds = match self.points_bounds(points, half_width + 1.0) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let mut mask = vec![0.0f32; bounds.width() * (bounds.y1 - bounds.y0) as usize];
+        for segment in points.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+            let segment_bounds = match self.bounds(
+                a.min(b) - Vec2::splat(half_width + 1.0),
+                a.max(b) + Vec2::splat(half_width + 1.0),
+            ) {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            for y in segment_bounds.y0..segment_bounds.y1 {
+                for x in segment_bounds.x0..segment_bounds.x1 {
+                    let p = glam::vec2(x as f32 + 0.5, y as f32 + 0.5);
+                    let coverage = half_width - distance_to_segment(p, a, b) + 0.5;
+                    let i = (y - bounds.y0) as usize * bounds.width() + (x - bounds.x0) as usize;
+                    mask[i] = mask[i].max(coverage.max(0.0));
+                }
+            }
+        }
+        self.fill_mask(bounds, color, &mask);
+    }
+}
+
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let length_squared = ab.dot(ab);
+    let t = if length_squared > 0.0 {
+        ((p - a).dot(ab) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (p - (a + ab * t)).length()
+}
+
+fn glyph(c: char) -> [u8; 8] {
+    font8x8::BASIC_FONTS
+        .get(c)
+        .or_else(|| font8x8::LATIN_FONTS.get(c))
+        .or_else(|| font8x8::GREEK_FONTS.get(c))
+        .or_else(|| font8x8::HIRAGANA_FONTS.get(c))
+        .or_else(|| font8x8::BASIC_FONTS.get('?'))
+        .unwrap_or([0; 8])
+}
+
+/// Bilinearly samples `image` at pixel coordinate `uv`, returning premultiplied RGBA.
+fn sample(image: &image::RgbaImage, uv: Vec2) -> [f32; 4] {
+    let x = uv.x - 0.5;
+    let y = uv.y - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let (fx, fy) = (x - x0, y - y0);
+    let mut out = [0.0; 4];
+    for &(dx, dy, weight) in &[
+        (0, 0, (1.0 - fx) * (1.0 - fy)),
+        (1, 0, fx * (1.0 - fy)),
+        (0, 1, (1.0 - fx) * fy),
+        (1, 1, fx * fy),
+    ] {
+        let px = x0 as i64 + dx;
+        let py = y0 as i64 + dy;
+        if px < 0 || py < 0 || px >= image.width() as i64 || py >= image.height() as i64 {
+            continue;
+        }
+        let pixel = image.get_pixel(px as u32, py as u32).0;
+        let alpha = pixel[3] as f32 / 255.0;
+        for c in 0..3 {
+            out[c] += pixel[c] as f32 / 255.0 * alpha * weight;
+        }
+        out[3] += alpha * weight;
+    }
+    out
+}
+
+impl Backend for CpuBackend {
+    fn size(&self) -> Vec2 {
+        glam::vec2(self.width as f32, self.height as f32)
+    }
+
+    fn clear(&mut self, color: Color) {
+        let pixel = [
+            (color.r * 255.0).round() as u8,
+            (color.g * 255.0).round() as u8,
+            (color.b * 255.0).round() as u8,
+            (color.a * 255.0).round() as u8,
+        ];
+        for chunk in self.data.chunks_exact_mut(4) {
+            chunk.copy_from_slice(&pixel);
+        }
+    }
+
+    fn draw_sprite(&mut self, texture: &Texture, params: SpriteParams) -> GameResult {
+        if params.scale.x == 0.0 || params.scale.y == 0.0 {
+            return Ok(());
+        }
+        let src = glam::vec2(params.src.x, params.src.y) * texture.size();
+        let size = glam::vec2(params.src.w, params.src.h) * texture.size();
+        let origin = params.offset * size;
+        let (sin, cos) = params.rotation.sin_cos();
+        let to_screen = |p: Vec2| {
+            let p = (p - origin) * params.scale;
+            params.dest + glam::vec2(p.x * cos - p.y * sin, p.x * sin + p.y * cos)
+        };
+        let corners = [
+            to_screen(glam::vec2(0.0, 0.0)),
+            to_screen(glam::vec2(size.x, 0.0)),
+            to_screen(glam::vec2(0.0, size.y)),
+            to_screen(size),
+        ];
+        let bounds = match self.points_bounds(&corners, 1.0) {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+
+        let image = texture.image();
+        for y in bounds.y0..bounds.y1 {
+            for x in bounds.x0..bounds.x1 {
+                let p = glam::vec2(x as f32 + 0.5, y as f32 + 0.5) - params.dest;
+                let p = glam::vec2(p.x * cos + p.y * sin, -p.x * sin + p.y * cos);
+                let uv = p / params.scale + origin;
+                if uv.x < -0.5 || uv.y < -0.5 || uv.x > size.x + 0.5 || uv.y > size.y + 0.5 {
+                    continue;
+                }
+                let [r, g, b, a] = sample(image, uv + src);
+                if a <= 0.0 {
+                    continue;
+                }
+                let color = Color::new(
+                    r / a * params.color.r,
+                    g / a * params.color.g,
+                    b / a * params.color.b,
+                    params.color.a,
+                );
+                self.blend(x, y, color, a);
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_circle(
+        &mut self,
+        mode: DrawMode,
+        center: Vec2,
+        radius: f32,
+        color: Color,
+    ) -> GameResult {
+        let padding = match mode {
+            DrawMode::Fill => radius + 1.0,
+            DrawMode::Stroke(width) => radius + width / 2.0 + 1.0,
+        };
+        let bounds = match self.bounds(center - Vec2::splat(padding), center + Vec2::splat(padding))
+        {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+        match mode {
+            DrawMode::Fill => self.fill_with(bounds, color, |p| {
+                (radius - (p - center).length() + 0.5).max(0.0)
+            }),
+            DrawMode::Stroke(width) => self.fill_with(bounds, color, |p| {
+                (width / 2.0 - ((p - center).length() - radius).abs() + 0.5).max(0.0)
+            }),
+        }
+        Ok(())
+    }
+
+    fn draw_polyline(&mut self, mode: DrawMode, points: &[Vec2], color: Color) -> GameResult {
+        if points.len() < 2 {
+            return Ok(());
+        }
+        match mode {
+            DrawMode::Fill => self.fill_polygon(points, color),
+            DrawMode::Stroke(width) => self.stroke_polyline(points, width, color),
+        }
+        Ok(())
+    }
+
+    fn draw_rect(&mut self, mode: DrawMode, pos: Vec2, size: Vec2, color: Color) -> GameResult {
+        match mode {
+            DrawMode::Fill => {
+                if let Some(bounds) = self.bounds(pos, pos + size) {
+                    self.fill_with(bounds, color, |_| 1.0);
+                }
+            }
+            DrawMode::Stroke(width) => {
+                let points = [
+                    pos,
+                    pos + glam::vec2(size.x, 0.0),
+                    pos + size,
+                    pos + glam::vec2(0.0, size.y),
+                    pos,
+                ];
+                self.stroke_polyline(&points, width, color);
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_text(&mut self, text: &str, pos: Vec2, size: f32, color: Color) -> GameResult {
+        let scale = size / 8.0;
+        for (i, c) in text.chars().enumerate() {
+            let glyph_pos = pos + glam::vec2(i as f32 * size, 0.0);
+            let bounds = match self.bounds(glyph_pos, glyph_pos + Vec2::splat(size)) {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            let rows = glyph(c);
+            // font8x8 stores each row with the leftmost pixel in the lowest bit
+            self.fill_with(bounds, color, |p| {
+                let local = (p - glyph_pos) / scale;
+                let (col, row) = (local.x as usize, local.y as usize);
+                if row < 8 && col < 8 && rows[row] & (1 << col) != 0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_clear_and_data_layout() {
+    let mut backend = CpuBackend::new(4, 2);
+    backend.clear(Color::new(1.0, 0.0, 0.0, 1.0));
+    assert_eq!(backend.data().len(), 4 * 2 * 4);
+    assert!(backend.data().chunks(4).all(|p| p == [255, 0, 0, 255]));
+}
+
+#[test]
+fn test_draw_circle_fill() {
+    let mut backend = CpuBackend::new(20, 20);
+    backend.clear(Color::new(0.0, 0.0, 0.0, 1.0));
+    backend
+        .draw_circle(
+            DrawMode::Fill,
+            glam::vec2(10.0, 10.0),
+            5.0,
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        )
+        .unwrap();
+    let pixel = |x: usize, y: usize| backend.data()[(y * 20 + x) * 4];
+    assert_eq!(pixel(10, 10), 255);
+    assert_eq!(pixel(0, 0), 0);
+    assert_eq!(pixel(17, 10), 0);
+}
+
+#[test]
+fn test_draw_polygon_fill() {
+    let mut backend = CpuBackend::new(10, 10);
+    backend.clear(Color::new(0.0, 0.0, 0.0, 1.0));
+    backend
+        .draw_polyline(
+            DrawMode::Fill,
+            &[
+                glam::vec2(2.0, 2.0),
+                glam::vec2(8.0, 2.0),
+                glam::vec2(8.0, 8.0),
+                glam::vec2(2.0, 8.0),
+            ],
+            Color::new(0.0, 1.0, 0.0, 1.0),
+        )
+        .unwrap();
+    let pixel = |x: usize, y: usize| backend.data()[(y * 10 + x) * 4 + 1];
+    assert_eq!(pixel(5, 5), 255);
+    assert_eq!(pixel(2, 2), 255);
+    assert_eq!(pixel(1, 5), 0);
+    assert_eq!(pixel(8, 5), 0);
+}
+
+#[test]
+fn test_draw_polygon_fill_non_finite() {
+    let mut backend = CpuBackend::new(10, 10);
+    backend
+        .draw_polyline(
+            DrawMode::Fill,
+            &[
+                glam::vec2(2.0, 2.0),
+                glam::vec2(f32::NAN, 5.0),
+                glam::vec2(f32::INFINITY, 8.0),
+                glam::vec2(2.0, 8.0),
+            ],
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_draw_sprite_scaled_tinted() {
+    let texture = Texture::new(image::RgbaImage::from_pixel(
+        2,
+        2,
+        image::Rgba([255, 255, 255, 255]),
+    ));
+    let mut backend = CpuBackend::new(10, 10);
+    backend.clear(Color::new(0.0, 0.0, 0.0, 1.0));
+    backend
+        .draw_sprite(
+            &texture,
+            SpriteParams::new()
+                .dest(glam::vec2(5.0, 5.0))
+                .offset(glam::vec2(0.5, 0.5))
+                .scale(glam::vec2(2.0, 2.0))
+                .color(Color::new(1.0, 0.0, 0.0, 1.0)),
+        )
+        .unwrap();
+    let pixel = |x: usize, y: usize| &backend.data()[(y * 10 + x) * 4..(y * 10 + x) * 4 + 3];
+    assert_eq!(pixel(4, 4), [255, 0, 0]);
+    assert_eq!(pixel(5, 5), [255, 0, 0]);
+    assert_eq!(pixel(0, 0), [0, 0, 0]);
+}
+
+#[test]
+fn test_draw_sprite_src() {
+    // Red on top, green underneath
+    let texture = Texture::new(image::RgbaImage::from_fn(2, 4, |_, y| {
+        if y < 2 {
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            image::Rgba([0, 255, 0, 255])
+        }
+    }));
+    let mut backend = CpuBackend::new(10, 10);
+    backend.clear(Color::new(0.0, 0.0, 0.0, 1.0));
+    backend
+        .draw_sprite(
+            &texture,
+            SpriteParams::new()
+                .src(ggez::graphics::Rect::new(0.0, 0.5, 1.0, 0.5))
+                .scale(glam::vec2(2.0, 2.0)),
+        )
+        .unwrap();
+    let pixel = |x: usize, y: usize| &backend.data()[(y * 10 + x) * 4..(y * 10 + x) * 4 + 3];
+    assert_eq!(pixel(1, 2), [0, 255, 0]);
+    assert_eq!(pixel(1, 5), [0, 0, 0]);
+    assert_eq!(pixel(5, 2), [0, 0, 0]);
+}
+
+#[test]
+fn test_blend_over_transparent() {
+    let mut backend = CpuBackend::new(2, 2);
+    backend
+        .draw_rect(
+            DrawMode::Fill,
+            glam::vec2(0.0, 0.0),
+            glam::vec2(2.0, 2.0),
+            Color::new(1.0, 0.0, 0.0, 0.5),
+        )
+        .unwrap();
+    assert_eq!(&backend.data()[..4], [255, 0, 0, 128]);
+}
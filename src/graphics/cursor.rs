@@ -0,0 +1,138 @@
+use std::f32::consts::TAU;
+
+use ggez::{graphics::Color, GameResult};
+use glam::Vec2;
+
+use super::{
+    backend::{Backend, SpriteParams},
+    fade::faded,
+    playfield::Playfield,
+};
+use crate::{
+    sampler::ReplaySampler,
+    skin::{Skin, SkinElement},
+};
+
+/// How much the cursor grows while a button is held, with CursorExpand.
+const EXPAND_SCALE: f32 = 1.3;
+
+/// How long the cursor takes to fully expand or shrink back, in milliseconds.
+const EXPAND_MS: f32 = 100.0;
+
+/// Time between two trail sprites, in milliseconds.
+const TRAIL_INTERVAL_MS: i32 = 16;
+
+/// How long a full turn takes with CursorRotate, in milliseconds.
+const ROTATION_PERIOD_MS: f32 = 10_000.0;
+
+fn rotation(current_ms: i32) -> f32 {
+    (current_ms as f32 / ROTATION_PERIOD_MS).fract() * TAU
+}
+
+/// The replay's cursor, drawn with the skin's cursor, cursormiddle and cursortrail.
+pub struct Cursor {
+    trail_length: usize,
+    /// Current size multiplier from CursorExpand, between 1 and `EXPAND_SCALE`
+    expand: f32,
+    last_ms: i32,
+}
+
+impl Cursor {
+    pub fn new(trail_length: usize) -> Self {
+        Self {
+            trail_length,
+            expand: 1.0,
+            last_ms: 0,
+        }
+    }
+
+    /// Grows or shrinks the cursor for the time passed since the last update.
+    pub fn update(&mut self, current_ms: i32, held: bool) {
+        let step = (current_ms - self.last_ms).max(0) as f32 / EXPAND_MS * (EXPAND_SCALE - 1.0);
+        self.expand = if held {
+            (self.expand + step).min(EXPAND_SCALE)
+        } else {
+            (self.expand - step).max(1.0)
+        };
+        self.last_ms = current_ms;
+    }
+
+    pub fn draw(
+        &self,
+        backend: &mut dyn Backend,
+        playfield: &Playfield,
+        skin: &Skin,
+        sampler: &ReplaySampler,
+        current_ms: i32,
+    ) -> GameResult {
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        let mut draw_element =
+            |element: &SkinElement, position: Vec2, scale: f32, rotation: f32, color: Color| {
+                let offset = if skin.ini.cursor_centre {
+                    glam::vec2(0.5, 0.5)
+                } else {
+                    glam::vec2(0.0, 0.0)
+                };
+                backend.draw_sprite(
+                    &element.texture,
+                    SpriteParams::new()
+                        .dest(playfield.to_screen(position))
+                        .offset(offset)
+                        .scale(element.size() * scale * playfield.scale() / element.texture.size())
+                        .rotation(rotation)
+                        .color(color),
+                )
+            };
+
+        let trail = skin.element("cursortrail");
+        for i in (1..=self.trail_length).rev() {
+            let trail_ms = current_ms - i as i32 * TRAIL_INTERVAL_MS;
+            // Older trail sprites are more transparent
+            let opacity = 1.0 - i as f32 / (self.trail_length + 1) as f32;
+            draw_element(
+                &trail,
+                sampler.sample(trail_ms).position,
+                1.0,
+                if skin.ini.cursor_trail_rotate {
+                    rotation(trail_ms)
+                } else {
+                    0.0
+                },
+                faded(white, opacity),
+            )?;
+        }
+
+        let position = sampler.sample(current_ms).position;
+        let expand = if skin.ini.cursor_expand {
+            self.expand
+        } else {
+            1.0
+        };
+        draw_element(
+            &skin.element("cursor"),
+            position,
+            expand,
+            if skin.ini.cursor_rotate {
+                rotation(current_ms)
+            } else {
+                0.0
+            },
+            white,
+        )?;
+        // The middle part stays still on top of the cursor
+        draw_element(&skin.element("cursormiddle"), position, 1.0, 0.0, white)
+    }
+}
+
+#[test]
+fn test_cursor_expand() {
+    let mut cursor = Cursor::new(0);
+    cursor.update(0, true);
+    assert_eq!(cursor.expand, 1.0);
+    cursor.update(50, true);
+    assert!((cursor.expand - 1.15).abs() < 1e-6);
+    cursor.update(200, true);
+    assert_eq!(cursor.expand, EXPAND_SCALE);
+    cursor.update(300, false);
+    assert_eq!(cursor.expand, 1.0);
+}
@@ -0,0 +1,79 @@
+use ggez::graphics::Color;
+
+/// How long objects take to fade in, shortened for approach rates above 9.
+pub fn fade_in_ms(ar_ms: i32) -> f32 {
+    400.0 * (ar_ms as f32 / 450.0).min(1.0)
+}
+
+/// Opacity ramping from 0 at `from_ms` to 1 at `to_ms`.
+fn ramp(current_ms: i32, from_ms: f32, to_ms: f32) -> f32 {
+    if to_ms <= from_ms {
+        return if current_ms as f32 >= to_ms { 1.0 } else { 0.0 };
+    }
+    ((current_ms as f32 - from_ms) / (to_ms - from_ms)).clamp(0.0, 1.0)
+}
+
+/// Opacity of a hit circle or slider head that has to be hit at `start_ms`.
+/// With Hidden it fades in over 40% of the approach time and straight back out over the next 30%.
+pub fn circle_opacity(current_ms: i32, start_ms: i32, ar_ms: i32, hidden: bool) -> f32 {
+    let appear_ms = (start_ms - ar_ms) as f32;
+    if hidden {
+        let fade_in_end = appear_ms + ar_ms as f32 * 0.4;
+        let fade_out_end = fade_in_end + ar_ms as f32 * 0.3;
+        ramp(current_ms, appear_ms, fade_in_end)
+            * (1.0 - ramp(current_ms, fade_in_end, fade_out_end))
+    } else {
+        ramp(current_ms, appear_ms, appear_ms + fade_in_ms(ar_ms))
+    }
+}
+
+/// Opacity of a slider body lasting from `start_ms` to `end_ms`.
+/// With Hidden it fades out gradually over the whole slider once it has faded in.
+pub fn slider_body_opacity(
+    current_ms: i32,
+    start_ms: i32,
+    end_ms: i32,
+    ar_ms: i32,
+    hidden: bool,
+) -> f32 {
+    let appear_ms = (start_ms - ar_ms) as f32;
+    if hidden {
+        let fade_in_end = appear_ms + ar_ms as f32 * 0.4;
+        ramp(current_ms, appear_ms, fade_in_end)
+            * (1.0 - ramp(current_ms, fade_in_end, end_ms as f32))
+    } else {
+        ramp(current_ms, appear_ms, appear_ms + fade_in_ms(ar_ms))
+    }
+}
+
+/// `color` with its alpha multiplied by `opacity`.
+pub fn faded(color: Color, opacity: f32) -> Color {
+    Color {
+        a: color.a * opacity,
+        ..color
+    }
+}
+
+#[test]
+fn test_circle_opacity() {
+    // AR 9: 600ms approach, 400ms fade in
+    assert_eq!(circle_opacity(400, 1000, 600, false), 0.0);
+    assert_eq!(circle_opacity(600, 1000, 600, false), 0.5);
+    assert_eq!(circle_opacity(800, 1000, 600, false), 1.0);
+    assert_eq!(circle_opacity(1000, 1000, 600, false), 1.0);
+
+    // Hidden: in by 640, out by 820
+    assert_eq!(circle_opacity(520, 1000, 600, true), 0.5);
+    assert_eq!(circle_opacity(640, 1000, 600, true), 1.0);
+    assert_eq!(circle_opacity(730, 1000, 600, true), 0.5);
+    assert_eq!(circle_opacity(820, 1000, 600, true), 0.0);
+    assert_eq!(circle_opacity(1000, 1000, 600, true), 0.0);
+}
+
+#[test]
+fn test_slider_body_opacity() {
+    assert_eq!(slider_body_opacity(1500, 1000, 2000, 600, false), 1.0);
+    assert_eq!(slider_body_opacity(640, 1000, 2000, 600, true), 1.0);
+    assert_eq!(slider_body_opacity(1320, 1000, 2000, 600, true), 0.5);
+    assert_eq!(slider_body_opacity(2000, 1000, 2000, 600, true), 0.0);
+}
@@ -0,0 +1,161 @@
+use std::rc::Rc;
+
+use ggez::{graphics::Color, GameResult};
+use glam::Vec2;
+use image::{Rgba, RgbaImage};
+
+use super::{
+    backend::{Backend, DrawMode, SpriteParams, Texture},
+    playfield::Playfield,
+};
+
+/// Radius of the lit area in osu!pixels, before it shrinks with combo.
+const BASE_RADIUS: f32 = 180.0;
+
+/// How long the flashlight takes to catch up with the cursor, in milliseconds.
+const FOLLOW_DELAY_MS: f32 = 120.0;
+
+/// How dark the lit area gets while a slider is being held.
+const SLIDER_DIM: f32 = 0.8;
+
+/// The overlay texture is this many times wider than the hole in it.
+const TEXTURE_SCALE: f32 = 16.0;
+
+/// Lit area radius for the current combo, shrinking at 100 and 200 combo like the game.
+pub fn radius_for_combo(combo: u32) -> f32 {
+    BASE_RADIUS
+        * if combo >= 200 {
+            0.625
+        } else if combo >= 100 {
+            0.8125
+        } else {
+            1.0
+        }
+}
+
+/// Black overlay with a soft-edged transparent hole in the middle, `TEXTURE_SCALE` times as wide as the hole.
+fn overlay_image() -> RgbaImage {
+    let size = 512;
+    let radius = size as f32 / TEXTURE_SCALE;
+    let center = size as f32 / 2.0;
+    RgbaImage::from_fn(size, size, |x, y| {
+        let distance = glam::vec2(x as f32 + 0.5 - center, y as f32 + 0.5 - center).length();
+        // Fully lit in the inner 80%, fading to black at the edge
+        let darkness = ((distance - radius * 0.8) / (radius * 0.2)).clamp(0.0, 1.0);
+        Rgba([0, 0, 0, (darkness * 255.0).round() as u8])
+    })
+}
+
+/// The Flashlight mod's overlay, hiding everything away from the cursor.
+pub struct Flashlight {
+    overlay: Rc<Texture>,
+    /// Position of the lit area in osu!pixels, trailing behind the cursor
+    position: Option<Vec2>,
+    last_ms: i32,
+}
+
+impl Default for Flashlight {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Flashlight {
+    pub fn new() -> Self {
+        Self {
+            overlay: Texture::new(overlay_image()),
+            position: None,
+            last_ms: 0,
+        }
+    }
+
+    /// Moves the lit area towards `cursor` for the time passed since the last update.
+    pub fn update(&mut self, current_ms: i32, cursor: Vec2) {
+        let position = match self.position {
+            Some(position) => {
+                // Eases out towards the cursor like the game, so fast flicks lag slightly behind
+                let t = ((current_ms - self.last_ms).max(0) as f32 / FOLLOW_DELAY_MS).min(1.0);
+                position + (cursor - position) * (t * (2.0 - t))
+            }
+            None => cursor,
+        };
+        self.position = Some(position);
+        self.last_ms = current_ms;
+    }
+
+    pub fn draw(
+        &self,
+        backend: &mut dyn Backend,
+        playfield: &Playfield,
+        combo: u32,
+        holding_slider: bool,
+    ) -> GameResult {
+        let screen = backend.size();
+        if holding_slider {
+            backend.draw_rect(
+                DrawMode::Fill,
+                glam::vec2(0.0, 0.0),
+                screen,
+                Color::new(0.0, 0.0, 0.0, SLIDER_DIM),
+            )?;
+        }
+
+        let center = playfield.to_screen(self.position.unwrap_or_else(|| glam::vec2(256.0, 192.0)));
+        let radius = radius_for_combo(combo) * playfield.scale();
+        let half_size = radius * TEXTURE_SCALE / 2.0;
+        backend.draw_sprite(
+            &self.overlay,
+            SpriteParams::new()
+                .dest(center)
+                .offset(glam::vec2(0.5, 0.5))
+                .scale(Vec2::splat(half_size * 2.0) / self.overlay.size()),
+        )?;
+
+        // Cover whatever the overlay doesn't reach on big screens
+        let black = Color::new(0.0, 0.0, 0.0, 1.0);
+        let (min, max) = (
+            center - Vec2::splat(half_size),
+            center + Vec2::splat(half_size),
+        );
+        let covers = [
+            (glam::vec2(0.0, 0.0), glam::vec2(screen.x, min.y)),
+            (
+                glam::vec2(0.0, max.y),
+                glam::vec2(screen.x, screen.y - max.y),
+            ),
+            (glam::vec2(0.0, min.y), glam::vec2(min.x, max.y - min.y)),
+            (
+                glam::vec2(max.x, min.y),
+                glam::vec2(screen.x - max.x, max.y - min.y),
+            ),
+        ];
+        for &(pos, size) in &covers {
+            if size.x > 0.0 && size.y > 0.0 {
+                backend.draw_rect(DrawMode::Fill, pos, size, black)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_radius_for_combo() {
+    assert_eq!(radius_for_combo(0), 180.0);
+    assert_eq!(radius_for_combo(99), 180.0);
+    assert_eq!(radius_for_combo(100), 146.25);
+    assert_eq!(radius_for_combo(250), 112.5);
+}
+
+#[test]
+fn test_flashlight_follow() {
+    let mut flashlight = Flashlight::new();
+    flashlight.update(0, glam::vec2(100.0, 100.0));
+    assert_eq!(flashlight.position, Some(glam::vec2(100.0, 100.0)));
+
+    // Half the follow delay covers three quarters of the way
+    flashlight.update(60, glam::vec2(200.0, 100.0));
+    assert_eq!(flashlight.position, Some(glam::vec2(175.0, 100.0)));
+
+    flashlight.update(1000, glam::vec2(200.0, 100.0));
+    assert_eq!(flashlight.position, Some(glam::vec2(200.0, 100.0)));
+}
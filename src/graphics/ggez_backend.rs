@@ -0,0 +1,94 @@
+use ggez::{
+    graphics::{self, Color, DrawParam, Drawable, Image, Mesh, MeshBuilder, PxScale, Rect, Text},
+    Context, GameResult,
+};
+use glam::Vec2;
+
+use super::backend::{Backend, DrawMode, SpriteParams, Texture};
+
+/// Backend drawing through ggez onto the current ggez render target.
+pub struct GgezBackend<'a> {
+    ctx: &'a mut Context,
+}
+
+impl<'a> GgezBackend<'a> {
+    pub fn new(ctx: &'a mut Context) -> Self {
+        Self { ctx }
+    }
+}
+
+fn to_ggez_mode(mode: DrawMode) -> graphics::DrawMode {
+    match mode {
+        DrawMode::Fill => graphics::DrawMode::fill(),
+        DrawMode::Stroke(width) => graphics::DrawMode::stroke(width),
+    }
+}
+
+impl<'a> Backend for GgezBackend<'a> {
+    fn size(&self) -> Vec2 {
+        let (w, h) = graphics::drawable_size(self.ctx);
+        glam::vec2(w, h)
+    }
+
+    fn clear(&mut self, color: Color) {
+        graphics::clear(self.ctx, color);
+    }
+
+    fn draw_sprite(&mut self, texture: &Texture, params: SpriteParams) -> GameResult {
+        let mut gpu_image = texture.gpu_image.borrow_mut();
+        if gpu_image.is_none() {
+            *gpu_image = Some(Image::from_rgba8(
+                self.ctx,
+                texture.image().width() as u16,
+                texture.image().height() as u16,
+                texture.image(),
+            )?);
+        }
+        gpu_image.as_ref().unwrap().draw(
+            self.ctx,
+            DrawParam::new()
+                .src(params.src)
+                .dest(params.dest)
+                .scale(params.scale)
+                .offset(params.offset)
+                .rotation(params.rotation)
+                .color(params.color),
+        )
+    }
+
+    fn draw_circle(
+        &mut self,
+        mode: DrawMode,
+        center: Vec2,
+        radius: f32,
+        color: Color,
+    ) -> GameResult {
+        MeshBuilder::new()
+            .circle(to_ggez_mode(mode), center, radius, 1.0, color)?
+            .build(self.ctx)?
+            .draw(self.ctx, DrawParam::new())
+    }
+
+    fn draw_polyline(&mut self, mode: DrawMode, points: &[Vec2], color: Color) -> GameResult {
+        MeshBuilder::new()
+            .polyline(to_ggez_mode(mode), points, color)?
+            .build(self.ctx)?
+            .draw(self.ctx, DrawParam::new())
+    }
+
+    fn draw_rect(&mut self, mode: DrawMode, pos: Vec2, size: Vec2, color: Color) -> GameResult {
+        Mesh::new_rectangle(
+            self.ctx,
+            to_ggez_mode(mode),
+            Rect::new(pos.x, pos.y, size.x, size.y),
+            color,
+        )?
+        .draw(self.ctx, DrawParam::new())
+    }
+
+    fn draw_text(&mut self, text: &str, pos: Vec2, size: f32, color: Color) -> GameResult {
+        let mut text = Text::new(text);
+        text.set_font(graphics::Font::default(), PxScale::from(size));
+        text.draw(self.ctx, DrawParam::new().dest(pos).color(color))
+    }
+}
@@ -0,0 +1,119 @@
+use ggez::{graphics::Color, GameResult};
+use glam::Vec2;
+use libosu::prelude::{HitObject, HitObjectKind};
+
+use super::{
+    backend::{Backend, SpriteParams},
+    fade::faded,
+    playfield::Playfield,
+};
+use crate::{
+    judgement::{HitResult, ObjectJudgement},
+    skin::Skin,
+    slider::{SliderPath, SliderTiming},
+    BeatmapData,
+};
+
+/// How long a judgement takes to pop in from a bigger size, in milliseconds.
+const POP_IN_MS: f32 = 100.0;
+
+/// Size a judgement pops in from.
+const POP_IN_SCALE: f32 = 1.5;
+
+/// How long a judgement takes to fade out at the end of its time on screen, in milliseconds.
+const FADE_OUT_MS: f32 = 200.0;
+
+/// How far a miss drops down over its time on screen, in osu!pixels.
+const MISS_DROP: f32 = 20.0;
+
+fn element_name(result: HitResult) -> &'static str {
+    match result {
+        HitResult::Hit300 => "hit300",
+        HitResult::Hit100 => "hit100",
+        HitResult::Hit50 => "hit50",
+        HitResult::Miss => "hit0",
+    }
+}
+
+/// How long the judgement stays on screen, in milliseconds.
+fn display_ms(result: HitResult) -> i32 {
+    match result {
+        HitResult::Miss => 800,
+        _ => 600,
+    }
+}
+
+/// Frame of an animation with `frame_count` frames to show `elapsed_ms` into it.
+/// Without a framerate from the skin the whole animation plays over a second.
+/// Judgements don't loop, so the last frame stays up once the animation is over.
+fn animation_frame(elapsed_ms: i32, frame_count: usize, framerate: Option<f32>) -> usize {
+    let framerate = framerate.unwrap_or(frame_count as f32);
+    let frame = (elapsed_ms.max(0) as f32 / 1000.0 * framerate) as usize;
+    frame.min(frame_count.saturating_sub(1))
+}
+
+/// Where on the playfield an object's judgement shows up: where the ball ended for sliders,
+/// the middle of the playfield for spinners.
+fn burst_position(map_data: &BeatmapData, object: &HitObject) -> Vec2 {
+    match &object.kind {
+        HitObjectKind::Circle => glam::vec2(object.pos.x as f32, object.pos.y as f32),
+        HitObjectKind::Slider(slider) => {
+            let timing = SliderTiming::new(&map_data.beatmap, object, slider);
+            SliderPath::new(object, slider).position_at(timing.progress_at(timing.end_ms() as f64))
+        }
+        HitObjectKind::Spinner(..) => glam::vec2(256.0, 192.0),
+    }
+}
+
+/// Draws the hit300, hit100, hit50 and hit0 judgements of every object judged recently enough to still be shown.
+pub fn draw_hit_bursts(
+    backend: &mut dyn Backend,
+    playfield: &Playfield,
+    map_data: &BeatmapData,
+    skin: &Skin,
+    current_ms: i32,
+    judgements: &[ObjectJudgement],
+) -> GameResult {
+    for (object, judgement) in map_data.beatmap.hit_objects.iter().zip(judgements) {
+        let duration_ms = display_ms(judgement.result);
+        let elapsed_ms = current_ms - judgement.time_ms;
+        if elapsed_ms < 0 || elapsed_ms >= duration_ms {
+            continue;
+        }
+
+        let frames = skin.animation(element_name(judgement.result));
+        let element =
+            &frames[animation_frame(elapsed_ms, frames.len(), skin.ini.animation_framerate)];
+        let mut position = burst_position(map_data, object);
+        // Animated judgements play as they are, single images get popped in
+        let mut scale = 1.0;
+        if frames.len() == 1 {
+            let pop = (elapsed_ms as f32 / POP_IN_MS).min(1.0);
+            scale = POP_IN_SCALE + (1.0 - POP_IN_SCALE) * pop;
+            if judgement.result == HitResult::Miss {
+                position.y += MISS_DROP * elapsed_ms as f32 / duration_ms as f32;
+            }
+        }
+        let opacity = ((duration_ms - elapsed_ms) as f32 / FADE_OUT_MS).min(1.0);
+        backend.draw_sprite(
+            &element.texture,
+            SpriteParams::new()
+                .dest(playfield.to_screen(position))
+                .offset(glam::vec2(0.5, 0.5))
+                .scale(element.size() * scale * playfield.scale() / element.texture.size())
+                .color(faded(Color::new(1.0, 1.0, 1.0, 1.0), opacity)),
+        )?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_animation_frame() {
+    // 10 frames over a second by default
+    assert_eq!(animation_frame(0, 10, None), 0);
+    assert_eq!(animation_frame(250, 10, None), 2);
+    assert_eq!(animation_frame(5000, 10, None), 9);
+    assert_eq!(animation_frame(250, 10, Some(60.0)), 9);
+    assert_eq!(animation_frame(100, 10, Some(20.0)), 2);
+    assert_eq!(animation_frame(100, 1, None), 0);
+}
@@ -0,0 +1,216 @@
+use ggez::{graphics::Color, GameResult};
+use glam::Vec2;
+use libosu::{prelude::Buttons, replay::ReplayAction};
+
+use super::{
+    backend::{Backend, SpriteParams},
+    playfield::Playfield,
+};
+use crate::skin::Skin;
+
+/// How long a key takes to sink in when pressed or pop back out when released, in milliseconds.
+const PRESS_ANIMATION_MS: f32 = 100.0;
+
+/// Size of a pressed key relative to a released one.
+const PRESSED_SCALE: f32 = 0.8;
+
+/// Size of a key in pixels on the 640x480 virtual screen.
+const KEY_SIZE: f32 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    K1,
+    K2,
+    M1,
+    M2,
+}
+
+impl Key {
+    pub const ALL: [Key; 4] = [Key::K1, Key::K2, Key::M1, Key::M2];
+
+    fn label(self) -> &'static str {
+        match self {
+            Key::K1 => "K1",
+            Key::K2 => "K2",
+            Key::M1 => "M1",
+            Key::M2 => "M2",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Key::K1 | Key::K2 => Color::new(1.0, 0.87, 0.4, 1.0),
+            Key::M1 | Key::M2 => Color::new(0.85, 0.6, 1.0, 1.0),
+        }
+    }
+}
+
+/// Keys held in a frame's `buttons`.
+/// Keyboard keys also set the matching mouse button's bit, so M1 and M2 only count as held without K1 and K2.
+pub fn held_keys(buttons: Buttons) -> Vec<Key> {
+    Key::ALL
+        .iter()
+        .copied()
+        .filter(|key| match key {
+            Key::K1 => buttons.contains(Buttons::K1),
+            Key::K2 => buttons.contains(Buttons::K2),
+            Key::M1 => buttons.contains(Buttons::M1) && !buttons.contains(Buttons::K1),
+            Key::M2 => buttons.contains(Buttons::M2) && !buttons.contains(Buttons::K2),
+        })
+        .collect()
+}
+
+/// Times a key was held, as `(press_ms, release_ms)`.
+type Presses = Vec<(i32, i32)>;
+
+/// The in-game input overlay on the right of the screen, counting presses of each key.
+pub struct KeyOverlay {
+    presses: [Presses; 4],
+}
+
+impl KeyOverlay {
+    pub fn new(frames: &[(i32, ReplayAction)]) -> Self {
+        let mut presses: [Presses; 4] = Default::default();
+        let mut held_since = [None; 4];
+        for (time_ms, action) in frames {
+            let held = held_keys(action.buttons);
+            for (i, key) in Key::ALL.iter().enumerate() {
+                match (held_since[i], held.contains(key)) {
+                    (None, true) => held_since[i] = Some(*time_ms),
+                    (Some(press_ms), false) => {
+                        presses[i].push((press_ms, *time_ms));
+                        held_since[i] = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // Keys still held at the end of the replay never get released
+        for (i, press_ms) in held_since.iter().enumerate() {
+            if let Some(press_ms) = press_ms {
+                presses[i].push((*press_ms, i32::MAX));
+            }
+        }
+        Self { presses }
+    }
+
+    /// Number of times `key` has been pressed by `current_ms`.
+    pub fn count(&self, key: Key, current_ms: i32) -> usize {
+        self.key_presses(key)
+            .partition_point(|(press_ms, _)| *press_ms <= current_ms)
+    }
+
+    /// How far `key` is pushed in at `current_ms`, from 0 when released to 1 when held.
+    fn pressed_amount(&self, key: Key, current_ms: i32) -> f32 {
+        let presses = self.key_presses(key);
+        let count = self.count(key, current_ms);
+        match count.checked_sub(1).map(|i| presses[i]) {
+            Some((press_ms, release_ms)) if current_ms < release_ms => {
+                ((current_ms - press_ms) as f32 / PRESS_ANIMATION_MS).min(1.0)
+            }
+            Some((press_ms, release_ms)) => {
+                // Released before the press animation finished, so it pops back out from where it got to
+                let depth = ((release_ms - press_ms) as f32 / PRESS_ANIMATION_MS).min(1.0);
+                (depth - (current_ms - release_ms) as f32 / PRESS_ANIMATION_MS).max(0.0)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn key_presses(&self, key: Key) -> &[(i32, i32)] {
+        &self.presses[Key::ALL.iter().position(|&k| k == key).unwrap()]
+    }
+
+    pub fn draw(
+        &self,
+        backend: &mut dyn Backend,
+        playfield: &Playfield,
+        skin: &Skin,
+        current_ms: i32,
+    ) -> GameResult {
+        let screen = backend.size();
+        let key_size = KEY_SIZE * playfield.scale();
+        let key_element = skin.element("inputoverlay-key");
+        for (i, &key) in Key::ALL.iter().enumerate() {
+            let center = glam::vec2(
+                screen.x - key_size * 0.75,
+                screen.y / 2.0 + (i as f32 - 1.5) * key_size * 1.1,
+            );
+            let pressed = self.pressed_amount(key, current_ms);
+            let scale = 1.0 - (1.0 - PRESSED_SCALE) * pressed;
+            let white = Color::new(1.0, 1.0, 1.0, 1.0);
+            let color = key.color();
+            // Fades to the key's colour as it gets pushed in
+            let tint = Color::new(
+                white.r + (color.r - white.r) * pressed,
+                white.g + (color.g - white.g) * pressed,
+                white.b + (color.b - white.b) * pressed,
+                1.0,
+            );
+            backend.draw_sprite(
+                &key_element.texture,
+                SpriteParams::new()
+                    .dest(center)
+                    .offset(glam::vec2(0.5, 0.5))
+                    .scale(Vec2::splat(key_size * scale) / key_element.texture.size())
+                    .color(tint),
+            )?;
+
+            let count = self.count(key, current_ms);
+            let text = if count == 0 {
+                key.label().to_owned()
+            } else {
+                count.to_string()
+            };
+            // Shrink long counts so they still fit in the key
+            let text_size = (key_size * 0.6).min(key_size * 0.9 / text.len() as f32) * scale;
+            backend.draw_text(
+                &text,
+                center - glam::vec2(text.len() as f32, 1.0) * text_size / 2.0,
+                text_size,
+                Color::new(0.0, 0.0, 0.0, 1.0),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+use crate::sampler::frame;
+
+#[test]
+fn test_held_keys() {
+    assert_eq!(held_keys(Buttons::K1 | Buttons::M1), [Key::K1]);
+    assert_eq!(held_keys(Buttons::M1), [Key::M1]);
+    assert_eq!(
+        held_keys(Buttons::K2 | Buttons::M2 | Buttons::M1),
+        [Key::K2, Key::M1]
+    );
+    assert_eq!(held_keys(Buttons::SMOKE), []);
+}
+
+#[test]
+fn test_key_counts() {
+    let k1 = Buttons::K1 | Buttons::M1;
+    let overlay = KeyOverlay::new(&[
+        frame(0, 0.0, 0.0, Buttons::empty()),
+        frame(100, 0.0, 0.0, k1),
+        frame(150, 0.0, 0.0, k1),
+        frame(200, 0.0, 0.0, Buttons::empty()),
+        frame(300, 0.0, 0.0, k1),
+        frame(320, 0.0, 0.0, Buttons::M1),
+        frame(400, 0.0, 0.0, Buttons::empty()),
+    ]);
+    assert_eq!(overlay.count(Key::K1, 99), 0);
+    assert_eq!(overlay.count(Key::K1, 100), 1);
+    assert_eq!(overlay.count(Key::K1, 1000), 2);
+    // Switching from K1 to the mouse button counts as a mouse press
+    assert_eq!(overlay.count(Key::M1, 1000), 1);
+    assert_eq!(overlay.count(Key::K2, 1000), 0);
+
+    assert_eq!(overlay.pressed_amount(Key::K1, 150), 0.5);
+    assert_eq!(overlay.pressed_amount(Key::K1, 200), 1.0);
+    assert_eq!(overlay.pressed_amount(Key::K1, 250), 0.5);
+    // Let go at 320 after only getting 20% in
+    assert!((overlay.pressed_amount(Key::K1, 330) - 0.1).abs() < 1e-6);
+}
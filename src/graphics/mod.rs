@@ -1,3 +1,13 @@
+pub mod backend;
 pub mod circle;
+pub mod cpu;
+pub mod cursor;
+pub mod fade;
+pub mod flashlight;
+pub mod ggez_backend;
+pub mod hit_burst;
+pub mod key_overlay;
+pub mod playfield;
 pub mod slider;
+pub mod slider_body;
 pub mod spinner;
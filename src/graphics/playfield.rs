@@ -0,0 +1,72 @@
+use glam::Vec2;
+
+/// Size of the playfield in osu!pixels.
+pub const PLAYFIELD_WIDTH: f32 = 512.0;
+pub const PLAYFIELD_HEIGHT: f32 = 384.0;
+
+/// The game lays out everything for a 640x480 screen and scales it up to the real resolution.
+const VIRTUAL_SCREEN_WIDTH: f32 = 640.0;
+const VIRTUAL_SCREEN_HEIGHT: f32 = 480.0;
+
+/// How far the game pushes the playfield down from the centre of the screen, in osu!pixels.
+const VERTICAL_OFFSET: f32 = 8.0;
+
+/// Maps osu!pixels to screen pixels the way the game does for a given resolution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Playfield {
+    scale: f32,
+    origin: Vec2,
+}
+
+impl Playfield {
+    pub fn new(screen_size: Vec2) -> Self {
+        // Fits the 4:3 area the game is laid out in, so wide screens get borders on the sides
+        // and tall ones above and below
+        let scale =
+            (screen_size.x / VIRTUAL_SCREEN_WIDTH).min(screen_size.y / VIRTUAL_SCREEN_HEIGHT);
+        let playfield_size = glam::vec2(PLAYFIELD_WIDTH, PLAYFIELD_HEIGHT);
+        let origin =
+            (screen_size - playfield_size * scale) / 2.0 + glam::vec2(0.0, VERTICAL_OFFSET * scale);
+        Self { scale, origin }
+    }
+
+    /// Screen pixels per osu!pixel, for sizes and widths.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Screen position of a point on the playfield.
+    pub fn to_screen(self, pos: Vec2) -> Vec2 {
+        self.origin + pos * self.scale
+    }
+}
+
+#[test]
+fn test_playfield_transform() {
+    let playfield = Playfield::new(glam::vec2(640.0, 480.0));
+    assert_eq!(playfield.scale(), 1.0);
+    assert_eq!(
+        playfield.to_screen(glam::vec2(0.0, 0.0)),
+        glam::vec2(64.0, 56.0)
+    );
+    assert_eq!(
+        playfield.to_screen(glam::vec2(PLAYFIELD_WIDTH, PLAYFIELD_HEIGHT)),
+        glam::vec2(576.0, 440.0)
+    );
+
+    // 16:9 keeps the height and centres horizontally
+    let playfield = Playfield::new(glam::vec2(1920.0, 1080.0));
+    assert_eq!(playfield.scale(), 2.25);
+    assert_eq!(
+        playfield.to_screen(glam::vec2(256.0, 192.0)),
+        glam::vec2(960.0, 540.0 + 8.0 * 2.25)
+    );
+
+    // Vertical video is limited by the width instead
+    let playfield = Playfield::new(glam::vec2(1080.0, 1920.0));
+    assert_eq!(playfield.scale(), 1080.0 / 640.0);
+    assert_eq!(
+        playfield.to_screen(glam::vec2(0.0, 0.0)).x,
+        1080.0 / 640.0 * 64.0
+    );
+}
@@ -1,121 +1,373 @@
-use ggez::graphics::{Color, DrawMode, DrawParam, Drawable};
-use libosu::prelude::{HitObject, SliderInfo};
+use std::{collections::HashMap, rc::Rc};
 
-use crate::BeatmapData;
+use ggez::{graphics::Color, GameResult};
+use glam::Vec2;
+use libosu::prelude::{Buttons, HitObject, Mods, SliderInfo};
 
-use super::circle::draw_circle;
+use crate::{
+    judgement::{ObjectJudgement, FOLLOW_RADIUS_SCALE},
+    sampler::ReplaySample,
+    skin::Skin,
+    slider::{beat_progress_at, SliderPath, SliderTiming},
+    BeatmapData,
+};
 
+use super::{
+    backend::{Backend, SpriteParams, Texture},
+    circle::{draw_circle, LEGACY_CIRCLE_RADIUS},
+    fade::{faded, slider_body_opacity},
+    playfield::Playfield,
+    slider_body::body_image,
+};
+
+/// Size of reverse arrows on the beat, shrinking back to normal size until the next one.
+const REVERSE_ARROW_PULSE: f32 = 1.3;
+
+/// How long the follow circle takes to grow to full size, in milliseconds.
+const FOLLOW_GROW_MS: f32 = 150.0;
+
+/// How far the ball travels along the path for each frame of its animation, in osu!pixels.
+const BALL_FRAME_DISTANCE: f32 = 10.0;
+
+/// Frame of the slider ball animation after the ball has travelled `distance` osu!pixels,
+/// so it rolls faster on faster sliders and loops for as long as the slider lasts.
+fn ball_frame(distance: f32, frame_count: usize) -> usize {
+    (distance.max(0.0) / BALL_FRAME_DISTANCE) as usize % frame_count.max(1)
+}
+
+/// Slider animations that can be turned on for a render, like the game's settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Snaking {
+    /// The body grows out of the head as the slider appears
+    pub snake_in: bool,
+    /// The body shrinks behind the ball on its last slide
+    pub snake_out: bool,
+}
+
+impl Snaking {
+    /// Part of the path (0 head, 1 tail) the body covers at `current_ms`.
+    pub fn visible_range(self, timing: &SliderTiming, current_ms: i32, ar_ms: i32) -> (f32, f32) {
+        let mut to = 1.0;
+        if self.snake_in {
+            // Fully grown a third of the way through the approach
+            let appear_ms = timing.start_ms - ar_ms;
+            to = ((current_ms - appear_ms) as f32 / (ar_ms as f32 / 3.0)).clamp(0.0, 1.0);
+        }
+        let mut from = 0.0;
+        if self.snake_out
+            && current_ms >= timing.start_ms
+            && timing.slide_at(current_ms as f64) == timing.slides - 1
+        {
+            let ball = timing.progress_at(current_ms as f64);
+            // The last slide goes back towards the head after an odd number of repeats
+            if timing.slides % 2 == 1 {
+                from = ball;
+            } else {
+                to = ball.min(to);
+            }
+        }
+        (from, to)
+    }
+}
+
+/// Slider paths and bodies kept between frames, since flattening the curve and rasterizing
+/// the body take far too long to redo every frame a slider is on screen.
+#[derive(Default)]
+pub struct SliderBodies {
+    bodies: HashMap<usize, SliderBody>,
+}
+
+impl SliderBodies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets the sliders that have faded out by `current_ms`.
+    pub fn evict(&mut self, current_ms: i32) {
+        self.bodies.retain(|_, body| current_ms < body.end_ms);
+    }
+}
+
+struct SliderBody {
+    path: SliderPath,
+    end_ms: i32,
+    rasterized: Option<RasterizedBody>,
+}
+
+/// Body texture of the part of the path that was visible when it was last rasterized.
+struct RasterizedBody {
+    playfield: Playfield,
+    range: (f32, f32),
+    texture: Rc<Texture>,
+    origin: Vec2,
+}
+
+impl SliderBody {
+    /// Texture of the body covering `range` of the path and the screen position of its top-left corner,
+    /// which is only rasterized again once the visible part of the path has moved by a pixel.
+    fn texture(
+        &mut self,
+        playfield: &Playfield,
+        range: (f32, f32),
+        radius: f32,
+        border: Color,
+        track: Color,
+    ) -> (Rc<Texture>, Vec2) {
+        let pixel = 1.0 / (self.path.length() * playfield.scale()).max(1.0);
+        let outdated = |body: &RasterizedBody| {
+            body.playfield != *playfield
+                || (body.range.0 - range.0).abs() >= pixel
+                || (body.range.1 - range.1).abs() >= pixel
+        };
+        if self.rasterized.as_ref().map(outdated).unwrap_or(true) {
+            let screen_points = self
+                .path
+                .sub_path(range.0, range.1)
+                .iter()
+                .map(|&p| playfield.to_screen(p))
+                .collect::<Vec<_>>();
+            let (image, origin) = body_image(&screen_points, radius, border, track);
+            self.rasterized = Some(RasterizedBody {
+                playfield: *playfield,
+                range,
+                texture: Texture::new(image),
+                origin,
+            });
+        }
+        let body = self.rasterized.as_ref().unwrap();
+        (body.texture.clone(), body.origin)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn draw_slider(
-    ctx: &mut ggez::Context,
+    backend: &mut dyn Backend,
+    playfield: &Playfield,
     map_data: &BeatmapData,
+    skin: &Skin,
+    bodies: &mut SliderBodies,
+    index: usize,
     current_ms: i32,
     object: &HitObject,
     slider: &SliderInfo,
     combo_color: Color,
-    combo_index: u8,
-) {
-    let mut points = Vec::with_capacity(slider.control_points.len() + 1);
-    points.push(object.pos);
-    points.extend(slider.control_points.iter());
-    let points =
-        libosu::spline::Spline::from_control(slider.kind, &points, Some(slider.pixel_length))
-            .spline_points;
-
-    let start_point = points[0];
-    let end_point = points[points.len() - 1];
-    let second_to_last_end_point = points[points.len() - 2];
-    let end_direction = (end_point.y - second_to_last_end_point.y)
-        .atan2(end_point.x - second_to_last_end_point.x)
-        + std::f64::consts::FRAC_PI_2;
-    let end_offset = libosu::math::Point {
-        x: end_direction.cos() * map_data.cs_osupixels as f64,
-        y: end_direction.sin() * map_data.cs_osupixels as f64,
+    combo_index: u32,
+    judgement: &ObjectJudgement,
+    cursor: &ReplaySample,
+    snaking: Snaking,
+) -> GameResult {
+    let timing = SliderTiming::new(&map_data.beatmap, object, slider);
+    let end_ms = timing.end_ms();
+    let body = bodies.bodies.entry(index).or_insert_with(|| SliderBody {
+        path: SliderPath::new(object, slider),
+        end_ms,
+        rasterized: None,
+    });
+    let opacity = slider_body_opacity(
+        current_ms,
+        object.start_time.0,
+        end_ms,
+        map_data.ar_ms,
+        map_data.mods.contains(Mods::Hidden),
+    );
+    let white = Color::new(1.0, 1.0, 1.0, 1.0);
+    let track_color = skin.ini.slider_track_override.unwrap_or(combo_color);
+    let circle_radius = map_data.cs_osupixels * playfield.scale();
+    let circle_size = Vec2::splat(circle_radius * 2.0);
+
+    let (from, to) = snaking.visible_range(&timing, current_ms, map_data.ar_ms);
+    let is_visible = |progress: f32| from <= progress && progress <= to;
+    let (texture, origin) = body.texture(
+        playfield,
+        (from, to),
+        circle_radius,
+        skin.ini.slider_border,
+        track_color,
+    );
+    let path = &body.path;
+    backend.draw_sprite(
+        &texture,
+        SpriteParams::new()
+            .dest(origin)
+            .color(faded(white, opacity)),
+    )?;
+
+    // Ticks disappear once the ball has gone over them
+    let tick = skin.element("sliderscorepoint");
+    for tick_ms in timing.tick_times() {
+        let progress = timing.progress_at(tick_ms);
+        if tick_ms <= current_ms as f64 || !is_visible(progress) {
+            continue;
+        }
+        backend.draw_sprite(
+            &tick.texture,
+            SpriteParams::new()
+                .dest(playfield.to_screen(path.position_at(progress)))
+                .offset(glam::vec2(0.5, 0.5))
+                .scale(tick.size() * (circle_radius / LEGACY_CIRCLE_RADIUS) / tick.texture.size())
+                .color(faded(white, opacity)),
+        )?;
+    }
+
+    // Where the ball ends up, which is back at the head after an even number of slides
+    let tail = timing.progress_at(end_ms as f64);
+    let tail_pos = playfield.to_screen(path.position_at(tail));
+    let end_circles = [
+        ("sliderendcircle", white),
+        ("sliderendcircleoverlay", combo_color),
+    ];
+    for &(name, color) in end_circles.iter().filter(|_| is_visible(tail)) {
+        let element = skin.element(name);
+        backend.draw_sprite(
+            &element.texture,
+            SpriteParams::new()
+                .dest(tail_pos)
+                .offset(glam::vec2(0.5, 0.5))
+                .scale(circle_size / element.texture.size())
+                .color(faded(color, opacity)),
+        )?;
+    }
+
+    let hit_ms = judgement.hit_ms(object);
+    draw_circle(
+        backend,
+        playfield,
+        map_data,
+        skin,
+        current_ms,
+        object,
+        combo_color,
+        combo_index,
+        hit_ms,
+    )?;
+
+    // Reverse arrows point back along the path from the ends the ball still has to turn around at
+    let (head_repeats, tail_repeats) = timing.upcoming_repeats(current_ms as f64);
+    let points = path.points();
+    if points.len() >= 2 {
+        let pulse = 1.0 - beat_progress_at(&map_data.beatmap, current_ms);
+        let scale = 1.0 + (REVERSE_ARROW_PULSE - 1.0) * pulse * pulse;
+        let arrow = skin.element("reversearrow");
+        let ends = [
+            (head_repeats && is_visible(0.0), points[0], points[1]),
+            (
+                tail_repeats && is_visible(1.0),
+                points[points.len() - 1],
+                points[points.len() - 2],
+            ),
+        ];
+        for &(_, end, towards) in ends.iter().filter(|(repeats, ..)| *repeats) {
+            let direction = towards - end;
+            backend.draw_sprite(
+                &arrow.texture,
+                SpriteParams::new()
+                    .dest(playfield.to_screen(end))
+                    .offset(glam::vec2(0.5, 0.5))
+                    .scale(circle_size * scale / arrow.texture.size())
+                    .rotation(direction.y.atan2(direction.x))
+                    .color(faded(white, opacity)),
+            )?;
+        }
+    }
+
+    if current_ms < object.start_time.0 || current_ms >= end_ms {
+        return Ok(());
+    }
+    let ball_pos = path.position_at(timing.progress_at(current_ms as f64));
+    let slides = if timing.slide_ms > 0.0 {
+        (current_ms - object.start_time.0) as f64 / timing.slide_ms
+    } else {
+        0.0
     };
+    let frames = skin.animation("sliderb");
+    let ball = &frames[ball_frame(slides as f32 * path.length(), frames.len())];
+    backend.draw_sprite(
+        &ball.texture,
+        SpriteParams::new()
+            .dest(playfield.to_screen(ball_pos))
+            .offset(glam::vec2(0.5, 0.5))
+            .scale(circle_size / ball.texture.size()),
+    )?;
 
-    let body_points = points
-        .windows(2)
-        .map(|p| {
-            let direction = (p[1].y - p[0].y).atan2(p[1].x - p[0].x) + std::f64::consts::FRAC_PI_2;
-            let offset = libosu::math::Point {
-                x: direction.cos() * map_data.cs_osupixels as f64,
-                y: direction.sin() * map_data.cs_osupixels as f64,
-            };
-            p[0] + offset
-        })
-        .chain(std::iter::once(end_point + end_offset))
-        .map(|p| glam::vec2(p.x as f32, p.y as f32))
-        .chain(
-            points
-                .windows(2)
-                .map(|p| {
-                    let direction =
-                        (p[1].y - p[0].y).atan2(p[1].x - p[0].x) + std::f64::consts::FRAC_PI_2;
-                    let offset = libosu::math::Point {
-                        x: direction.cos() * map_data.cs_osupixels as f64,
-                        y: direction.sin() * map_data.cs_osupixels as f64,
-                    };
-                    p[0] - offset
-                })
-                .chain(std::iter::once(end_point - end_offset))
-                .map(|p| glam::vec2(p.x as f32, p.y as f32))
-                .rev(),
-        )
-        .collect::<Vec<_>>();
-
-    let fill_color = Color {
-        r: 3.0 / 255.0,
-        g: 3.0 / 255.0,
-        b: 12.0 / 255.0,
-        a: (3.0 + 3.0 + 12.0) / 255.0,
+    // The follow circle grows out of the ball after the head is hit, for as long as the cursor keeps up with it
+    let tracking = cursor.buttons.intersects(Buttons::M1 | Buttons::M2)
+        && cursor.position.distance(ball_pos) <= map_data.cs_osupixels * FOLLOW_RADIUS_SCALE;
+    match hit_ms {
+        Some(hit_ms) if tracking && current_ms >= hit_ms => {
+            let grow = ((current_ms - hit_ms) as f32 / FOLLOW_GROW_MS).min(1.0);
+            let scale = 1.0 + (FOLLOW_RADIUS_SCALE - 1.0) * grow * (2.0 - grow);
+            let follow = skin.element("sliderfollowcircle");
+            backend.draw_sprite(
+                &follow.texture,
+                SpriteParams::new()
+                    .dest(playfield.to_screen(ball_pos))
+                    .offset(glam::vec2(0.5, 0.5))
+                    .scale(circle_size * scale / follow.texture.size())
+                    .color(faded(white, grow)),
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+#[test]
+fn test_snaking_visible_range() {
+    let timing = SliderTiming {
+        start_ms: 1000,
+        slides: 1,
+        slide_ms: 400.0,
+        tick_ms: 0.0,
     };
-    let stroke_color = Color {
-        r: 120.0 / 255.0,
-        g: 120.0 / 255.0,
-        b: 120.0 / 255.0,
-        a: 1.0,
+    let both = Snaking {
+        snake_in: true,
+        snake_out: true,
+    };
+    assert_eq!(
+        Snaking::default().visible_range(&timing, 500, 600),
+        (0.0, 1.0)
+    );
+    assert_eq!(both.visible_range(&timing, 400, 600), (0.0, 0.0));
+    assert_eq!(both.visible_range(&timing, 500, 600), (0.0, 0.5));
+    assert_eq!(both.visible_range(&timing, 1100, 600), (0.25, 1.0));
+
+    // Going back to the head on the last slide
+    let repeating = SliderTiming {
+        slides: 2,
+        ..timing
     };
+    assert_eq!(both.visible_range(&repeating, 1200, 600), (0.0, 1.0));
+    assert_eq!(both.visible_range(&repeating, 1500, 600), (0.0, 0.75));
+}
+
+#[test]
+fn test_slider_bodies_cache() {
+    let white = Color::new(1.0, 1.0, 1.0, 1.0);
+    let playfield = Playfield::new(glam::vec2(640.0, 480.0));
+    let mut bodies = SliderBodies::new();
+    let body = bodies.bodies.entry(0).or_insert_with(|| SliderBody {
+        path: SliderPath::from_points(vec![glam::vec2(0.0, 0.0), glam::vec2(100.0, 0.0)]),
+        end_ms: 1000,
+        rasterized: None,
+    });
+    let (first, _) = body.texture(&playfield, (0.0, 0.5), 10.0, white, white);
+    // Less than a pixel further along the path
+    let (same, _) = body.texture(&playfield, (0.0, 0.505), 10.0, white, white);
+    assert!(Rc::ptr_eq(&first, &same));
+    let (grown, _) = body.texture(&playfield, (0.0, 0.6), 10.0, white, white);
+    assert!(!Rc::ptr_eq(&first, &grown));
+    assert!(grown.width() > first.width());
+
+    bodies.evict(999);
+    assert_eq!(bodies.bodies.len(), 1);
+    bodies.evict(1000);
+    assert!(bodies.bodies.is_empty());
+}
 
-    ggez::graphics::MeshBuilder::new()
-        .circle(
-            DrawMode::fill(),
-            glam::vec2(start_point.x as f32, start_point.y as f32),
-            map_data.cs_osupixels,
-            1.0,
-            fill_color,
-        )
-        .unwrap()
-        .circle(
-            DrawMode::stroke(5.0),
-            glam::vec2(start_point.x as f32, start_point.y as f32),
-            map_data.cs_osupixels,
-            1.0,
-            stroke_color,
-        )
-        .unwrap()
-        .polyline(DrawMode::fill(), &body_points, fill_color)
-        .unwrap()
-        .polyline(DrawMode::stroke(5.0), &body_points, stroke_color)
-        .unwrap()
-        .circle(
-            DrawMode::fill(),
-            glam::vec2(end_point.x as f32, end_point.y as f32),
-            map_data.cs_osupixels,
-            1.0,
-            fill_color,
-        )
-        .unwrap()
-        .circle(
-            DrawMode::stroke(5.0),
-            glam::vec2(end_point.x as f32, end_point.y as f32),
-            map_data.cs_osupixels,
-            1.0,
-            stroke_color,
-        )
-        .unwrap()
-        .build(ctx)
-        .unwrap()
-        .draw(ctx, DrawParam::new())
-        .unwrap();
-
-    draw_circle(ctx, map_data, current_ms, object, combo_color, combo_index);
+#[test]
+fn test_ball_frame() {
+    assert_eq!(ball_frame(0.0, 10), 0);
+    assert_eq!(ball_frame(25.0, 10), 2);
+    // Loops back to the first frame
+    assert_eq!(ball_frame(105.0, 10), 0);
+    assert_eq!(ball_frame(1000.0, 1), 0);
 }
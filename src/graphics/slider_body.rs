@@ -0,0 +1,150 @@
+use ggez::graphics::Color;
+use glam::Vec2;
+use image::{Rgba, RgbaImage};
+
+/// Width of the border as a fraction of the body's radius.
+const BORDER_PORTION: f32 = 0.128;
+
+/// Opacity of the track inside the border.
+const TRACK_ALPHA: f32 = 0.7;
+
+/// Colour of the track at `position`, going from 0 on the inside of the border to 1 in the middle.
+/// Like the game, the track is a gradient from a slightly darker combo colour to a much lighter one.
+fn track_color(track: Color, position: f32) -> Color {
+    let outer = Color::new(track.r / 1.1, track.g / 1.1, track.b / 1.1, track.a);
+    let inner = Color::new(
+        (track.r * 1.5).min(1.0),
+        (track.g * 1.5).min(1.0),
+        (track.b * 1.5).min(1.0),
+        track.a,
+    );
+    Color::new(
+        outer.r + (inner.r - outer.r) * position,
+        outer.g + (inner.g - outer.g) * position,
+        outer.b + (inner.b - outer.b) * position,
+        track.a * TRACK_ALPHA,
+    )
+}
+
+/// Distance from `p` to the segment from `a` to `b`.
+fn segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let length_squared = ab.length_squared();
+    let t = if length_squared > 0.0 {
+        ((p - a).dot(ab) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (a + ab * t - p).length()
+}
+
+/// Draws a slider body of `radius` around the path through `points`, with round joins and caps.
+/// Returns the image along with the position of its top-left corner, in the same space as `points`.
+///
+/// The body is drawn from the distance to the path rather than by stroking it,
+/// so tight curves where the path overlaps itself don't get drawn twice.
+pub fn body_image(points: &[Vec2], radius: f32, border: Color, track: Color) -> (RgbaImage, Vec2) {
+    if points.is_empty() || radius <= 0.0 {
+        return (RgbaImage::new(1, 1), glam::vec2(0.0, 0.0));
+    }
+    let (min, max) = points
+        .iter()
+        .fold((points[0], points[0]), |(min, max), &p| {
+            (min.min(p), max.max(p))
+        });
+    let origin = (min - Vec2::splat(radius + 1.0)).floor();
+    let size = (max + Vec2::splat(radius + 1.0)).ceil() - origin;
+    let (width, height) = (size.x as u32, size.y as u32);
+
+    let mut distances = vec![f32::INFINITY; (width * height) as usize];
+    let single_point = [points[0], points[0]];
+    let segments = if points.len() == 1 {
+        &single_point[..]
+    } else {
+        points
+    };
+    for segment in segments.windows(2) {
+        let (a, b) = (segment[0] - origin, segment[1] - origin);
+        // Only pixels close enough to the segment can be part of the body
+        let x0 = (a.x.min(b.x) - radius - 1.0).max(0.0) as u32;
+        let y0 = (a.y.min(b.y) - radius - 1.0).max(0.0) as u32;
+        let x1 = ((a.x.max(b.x) + radius + 1.0).ceil() as u32).min(width);
+        let y1 = ((a.y.max(b.y) + radius + 1.0).ceil() as u32).min(height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = glam::vec2(x as f32 + 0.5, y as f32 + 0.5);
+                let distance = &mut distances[(y * width + x) as usize];
+                *distance = distance.min(segment_distance(p, a, b));
+            }
+        }
+    }
+
+    let image = RgbaImage::from_fn(width, height, |x, y| {
+        let distance = distances[(y * width + x) as usize];
+        // Antialiased outer edge
+        let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+        if coverage <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let position = 1.0 - distance / radius;
+        let color = if position < BORDER_PORTION {
+            border
+        } else {
+            track_color(track, (position - BORDER_PORTION) / (1.0 - BORDER_PORTION))
+        };
+        let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Rgba([
+            to_u8(color.r),
+            to_u8(color.g),
+            to_u8(color.b),
+            to_u8(color.a * coverage),
+        ])
+    });
+    (image, origin)
+}
+
+#[test]
+fn test_body_image() {
+    let border = Color::new(1.0, 1.0, 1.0, 1.0);
+    let track = Color::new(0.4, 0.0, 0.0, 1.0);
+    let (image, origin) = body_image(
+        &[glam::vec2(100.0, 100.0), glam::vec2(200.0, 100.0)],
+        20.0,
+        border,
+        track,
+    );
+    assert_eq!(origin, glam::vec2(79.0, 79.0));
+    assert_eq!(image.dimensions(), (142, 42));
+
+    // Centre of the path is the lightest part of the track
+    assert_eq!(image.get_pixel(71, 21).0, [151, 0, 0, 179]);
+    // Border along the edge
+    assert_eq!(image.get_pixel(71, 2).0, [255, 255, 255, 255]);
+    // Round caps leave the corners empty
+    assert_eq!(image.get_pixel(2, 2).0[3], 0);
+    assert_eq!(image.get_pixel(2, 21).0, [255, 255, 255, 255]);
+}
+
+#[test]
+fn test_body_image_overlap() {
+    // Doubling back over the same path gives the same body as going over it once
+    let border = Color::new(1.0, 1.0, 1.0, 1.0);
+    let track = Color::new(0.0, 0.5, 0.0, 1.0);
+    let (once, _) = body_image(
+        &[glam::vec2(0.0, 0.0), glam::vec2(50.0, 0.0)],
+        10.0,
+        border,
+        track,
+    );
+    let (twice, _) = body_image(
+        &[
+            glam::vec2(0.0, 0.0),
+            glam::vec2(50.0, 0.0),
+            glam::vec2(0.0, 0.0),
+        ],
+        10.0,
+        border,
+        track,
+    );
+    assert_eq!(once, twice);
+}
@@ -1,37 +1,147 @@
 use ggez::{
-    graphics::{drawable_size, Color, DrawMode, DrawParam, Drawable},
-    mint,
+    graphics::{Color, Rect},
+    GameResult,
 };
-use libosu::prelude::HitObject;
+use glam::Vec2;
+use libosu::prelude::{HitObject, Mods};
 
-use crate::BeatmapData;
+use super::{
+    backend::{Backend, SpriteParams},
+    fade::{circle_opacity, faded},
+    playfield::Playfield,
+};
+use crate::{
+    skin::{Skin, SkinElement},
+    spinner::{SpinnerSpins, MAX_RPM},
+    BeatmapData,
+};
+
+/// Spinner images are made for a 1024x768 screen, which is scaled down to the 640x480 virtual screen.
+const SPINNER_SCALE: f32 = 640.0 / 1024.0;
 
+/// Number of bars in the spinner metre.
+const METRE_BARS: f32 = 10.0;
+
+#[allow(clippy::too_many_arguments)]
 pub fn draw_spinner(
-    ctx: &mut ggez::Context,
-    _map_data: &BeatmapData,
-    _current_ms: i32,
-    _object: &HitObject,
-) {
-    ggez::graphics::Mesh::new_circle(
-        ctx,
-        DrawMode::stroke(1.0),
-        mint::Point2 { x: 0.0, y: 0.0 },
-        10.0,
-        1.0,
-        Color {
-            r: 0.0,
-            g: 0.0,
-            b: 1.0,
-            a: 1.0,
-        },
+    backend: &mut dyn Backend,
+    playfield: &Playfield,
+    map_data: &BeatmapData,
+    skin: &Skin,
+    current_ms: i32,
+    object: &HitObject,
+    end_ms: i32,
+    spins: &SpinnerSpins,
+) -> GameResult {
+    let start_ms = object.start_time.0;
+    let opacity = circle_opacity(current_ms, start_ms, map_data.ar_ms, false);
+    let white = faded(Color::new(1.0, 1.0, 1.0, 1.0), opacity);
+    let center = playfield.to_screen(glam::vec2(256.0, 192.0));
+    let rotation = spins.rotation_at(current_ms);
+    let rpm = spins.rpm_at(current_ms);
+
+    let draw_element =
+        |backend: &mut dyn Backend, element: &SkinElement, scale: f32, rotation: f32| {
+            backend.draw_sprite(
+                &element.texture,
+                SpriteParams::new()
+                    .dest(center)
+                    .offset(glam::vec2(0.5, 0.5))
+                    .scale(
+                        element.size() * SPINNER_SCALE * scale * playfield.scale()
+                            / element.texture.size(),
+                    )
+                    .rotation(rotation)
+                    .color(white),
+            )
+        };
+
+    // Newer skins replace the background and circle with a bottom and top that turn at different speeds
+    if skin.new_style_spinner {
+        draw_element(
+            backend,
+            &skin.element("spinner-bottom"),
+            1.0,
+            rotation / 3.0,
+        )?;
+        draw_element(backend, &skin.element("spinner-top"), 1.0, rotation)?;
+        draw_element(backend, &skin.element("spinner-middle2"), 1.0, 0.0)?;
+    } else {
+        draw_element(backend, &skin.element("spinner-background"), 1.0, 0.0)?;
+        draw_metre(
+            backend,
+            playfield,
+            &skin.element("spinner-metre"),
+            center,
+            (rpm / MAX_RPM).min(1.0),
+            white,
+        )?;
+        draw_element(backend, &skin.element("spinner-circle"), 1.0, rotation)?;
+    }
+
+    if !map_data.mods.contains(Mods::Hidden) && current_ms < end_ms {
+        // Shrinks into the middle by the time the spinner ends
+        let remaining =
+            (end_ms - current_ms.max(start_ms)) as f32 / (end_ms - start_ms).max(1) as f32;
+        draw_element(
+            backend,
+            &skin.element("spinner-approachcircle"),
+            remaining,
+            0.0,
+        )?;
+    }
+
+    if current_ms >= start_ms && spins.spins_at(current_ms) >= spins.required() as f32 {
+        draw_element(backend, &skin.element("spinner-clear"), 1.0, 0.0)?;
+    }
+    let bonus = spins.bonus_spins_at(current_ms);
+    if bonus > 0 {
+        let text = (bonus * 1000).to_string();
+        let size = 32.0 * playfield.scale();
+        backend.draw_text(
+            &text,
+            center + glam::vec2(-(text.len() as f32) * size / 2.0, 80.0 * playfield.scale()),
+            size,
+            white,
+        )?;
+    }
+
+    let text = format!("RPM: {:.0}", rpm);
+    let size = 16.0 * playfield.scale();
+    let screen = backend.size();
+    backend.draw_text(
+        &text,
+        glam::vec2(
+            (screen.x - text.len() as f32 * size) / 2.0,
+            screen.y - size * 2.0,
+        ),
+        size,
+        white,
     )
-    .unwrap()
-    .draw(
-        ctx,
-        DrawParam::new().dest(mint::Point2 {
-            x: drawable_size(ctx).0 / 2.0,
-            y: drawable_size(ctx).1 / 2.0,
-        }),
+}
+
+/// Draws the bottom `fill` of the spinner metre, rounded down to whole bars.
+fn draw_metre(
+    backend: &mut dyn Backend,
+    playfield: &Playfield,
+    metre: &SkinElement,
+    center: Vec2,
+    fill: f32,
+    color: Color,
+) -> GameResult {
+    let visible = ((fill * METRE_BARS).floor() / METRE_BARS).min(1.0);
+    if visible <= 0.0 {
+        return Ok(());
+    }
+    let scale = metre.size() * SPINNER_SCALE * playfield.scale() / metre.texture.size();
+    backend.draw_sprite(
+        &metre.texture,
+        SpriteParams::new()
+            .src(Rect::new(0.0, 1.0 - visible, 1.0, visible))
+            // Lined up with the bottom of the full metre, which covers the screen like the background
+            .dest(center + glam::vec2(0.0, metre.texture.height() / 2.0) * scale)
+            .offset(glam::vec2(0.5, 1.0))
+            .scale(scale)
+            .color(color),
     )
-    .unwrap();
 }
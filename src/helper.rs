@@ -11,6 +11,20 @@ pub fn cs_to_osupixels(cs: f32) -> f32 {
     54.4 - 4.48 * cs
 }
 
+/// Hit windows for 300s, 100s and 50s, in milliseconds either side of an object's time.
+pub fn od_to_hit_windows(od: f32) -> (f32, f32, f32) {
+    (80.0 - 6.0 * od, 140.0 - 8.0 * od, 200.0 - 10.0 * od)
+}
+
+/// Full spins per second needed to clear a spinner.
+pub fn od_to_spins_per_second(od: f32) -> f32 {
+    if od >= 5.0 {
+        5.0 + (od - 5.0) * 0.5
+    } else {
+        3.0 + od * 0.4
+    }
+}
+
 #[test]
 fn test_ar_to_ms() {
     assert_eq!(ar_to_ms(11.0), 300);
@@ -28,3 +42,17 @@ fn test_cs_to_osupixels() {
     assert_eq!(cs_to_osupixels(6.0), 27.52);
     assert_eq!(cs_to_osupixels(4.0), 36.480003); // floating point precision lol
 }
+
+#[test]
+fn test_od_to_hit_windows() {
+    assert_eq!(od_to_hit_windows(0.0), (80.0, 140.0, 200.0));
+    assert_eq!(od_to_hit_windows(5.0), (50.0, 100.0, 150.0));
+    assert_eq!(od_to_hit_windows(10.0), (20.0, 60.0, 100.0));
+}
+
+#[test]
+fn test_od_to_spins_per_second() {
+    assert_eq!(od_to_spins_per_second(0.0), 3.0);
+    assert_eq!(od_to_spins_per_second(5.0), 5.0);
+    assert_eq!(od_to_spins_per_second(10.0), 7.5);
+}
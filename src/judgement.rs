@@ -0,0 +1,411 @@
+use glam::Vec2;
+use libosu::{prelude::*, replay::ReplayAction};
+
+use crate::{
+    helper::od_to_hit_windows,
+    slider::{SliderPath, SliderTiming},
+    spinner::{required_spins, SpinnerSpins},
+    BeatmapData,
+};
+
+/// Follow circle radius relative to the circle radius, past which a held slider is dropped.
+pub const FOLLOW_RADIUS_SCALE: f32 = 2.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitResult {
+    Hit300,
+    Hit100,
+    Hit50,
+    Miss,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliderEventKind {
+    Head,
+    Tick,
+    Repeat,
+    Tail,
+}
+
+/// A combo-affecting point of a slider, and whether the player caught it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SliderEvent {
+    pub kind: SliderEventKind,
+    pub time_ms: i32,
+    pub hit: bool,
+}
+
+/// A full turn of a spinner, worth bonus score once the spinner has been cleared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpinnerSpin {
+    pub time_ms: i32,
+    pub bonus: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectJudgement {
+    pub result: HitResult,
+    /// When the result was decided: the hit itself, the end of a slider or spinner, or the end of the hit window
+    pub time_ms: i32,
+    /// How far from the object's time the object (or slider head) was hit, `None` if it wasn't
+    pub hit_offset: Option<i32>,
+    /// Heads, ticks, repeats and tails of sliders in time order, empty for other objects
+    pub slider_events: Vec<SliderEvent>,
+    /// How a spinner was spun over its lifetime, `None` for other objects
+    pub spinner: Option<SpinnerSpins>,
+}
+
+impl ObjectJudgement {
+    /// When `object` (or its slider head) was hit, `None` if it wasn't.
+    pub fn hit_ms(&self, object: &HitObject) -> Option<i32> {
+        self.hit_offset.map(|offset| object.start_time.0 + offset)
+    }
+
+    /// Full spins of a spinner in time order, empty for other objects.
+    pub fn spinner_spins(&self) -> Vec<SpinnerSpin> {
+        match &self.spinner {
+            Some(spins) => spins
+                .spin_times()
+                .into_iter()
+                .enumerate()
+                .map(|(i, time_ms)| SpinnerSpin {
+                    time_ms,
+                    bonus: i as u32 >= spins.required(),
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Buttons pressed in a frame that weren't held in the previous one.
+/// K1 and K2 always come with M1 and M2 set, so only the mouse bits are compared
+/// to avoid counting a key press twice.
+pub fn pressed_buttons(prev: Buttons, current: Buttons) -> Buttons {
+    current & !prev & (Buttons::M1 | Buttons::M2)
+}
+
+fn is_held(buttons: Buttons) -> bool {
+    buttons.intersects(Buttons::M1 | Buttons::M2)
+}
+
+fn judge_offset(offset: i32, windows: (f32, f32, f32)) -> Option<HitResult> {
+    let offset = offset.abs() as f32;
+    if offset <= windows.0 {
+        Some(HitResult::Hit300)
+    } else if offset <= windows.1 {
+        Some(HitResult::Hit100)
+    } else if offset <= windows.2 {
+        Some(HitResult::Hit50)
+    } else {
+        None
+    }
+}
+
+/// Times at which circles and slider heads were hit.
+/// Only the earliest unjudged object can be hit, later objects are notelocked until it is hit or missed.
+fn judge_heads(
+    objects: &[HitObject],
+    frames: &[(i32, ReplayAction)],
+    radius: f32,
+    window_50: i32,
+) -> Vec<Option<i32>> {
+    let mut hits = vec![None; objects.len()];
+    let mut next = 0;
+    let mut prev_buttons = Buttons::empty();
+    for (time, action) in frames {
+        let pressed = pressed_buttons(prev_buttons, action.buttons);
+        prev_buttons = action.buttons;
+
+        for _ in 0..pressed.bits().count_ones() {
+            while next < objects.len()
+                && (matches!(objects[next].kind, HitObjectKind::Spinner(..))
+                    || *time > objects[next].start_time.0 + window_50)
+            {
+                next += 1;
+            }
+            let object = match objects.get(next) {
+                Some(object) => object,
+                None => break,
+            };
+            let distance = (glam::vec2(action.x, action.y)
+                - glam::vec2(object.pos.x as f32, object.pos.y as f32))
+            .length();
+            if distance <= radius && (*time - object.start_time.0).abs() <= window_50 {
+                hits[next] = Some(*time);
+                next += 1;
+            }
+        }
+    }
+    hits
+}
+
+/// Whether the cursor in the latest frame at or before `time_ms` is holding a key within `radius` of `pos`.
+fn is_tracking(frames: &[(i32, ReplayAction)], time_ms: i32, pos: Vec2, radius: f32) -> bool {
+    let i = frames.partition_point(|(time, _)| *time <= time_ms);
+    match i.checked_sub(1).and_then(|i| frames.get(i)) {
+        Some((_, action)) => {
+            is_held(action.buttons) && (glam::vec2(action.x, action.y) - pos).length() <= radius
+        }
+        None => false,
+    }
+}
+
+fn judge_slider(
+    beatmap: &Beatmap,
+    object: &HitObject,
+    slider: &SliderInfo,
+    frames: &[(i32, ReplayAction)],
+    head_hit: Option<i32>,
+    follow_radius: f32,
+) -> ObjectJudgement {
+    let path = SliderPath::new(object, slider);
+    let timing = SliderTiming::new(beatmap, object, slider);
+
+    let mut checks: Vec<(SliderEventKind, f64)> = timing
+        .tick_times()
+        .into_iter()
+        .map(|time| (SliderEventKind::Tick, time))
+        .chain(
+            timing
+                .repeat_times()
+                .into_iter()
+                .map(|time| (SliderEventKind::Repeat, time)),
+        )
+        .collect();
+    // The game checks the tail slightly early so it can't be dropped on the last frame
+    let tail_ms =
+        (timing.end_ms() as f64 - 36.0).max(timing.start_ms as f64 + timing.duration_ms() / 2.0);
+    checks.push((SliderEventKind::Tail, tail_ms));
+    checks.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+    let mut events = vec![SliderEvent {
+        kind: SliderEventKind::Head,
+        time_ms: object.start_time.0,
+        hit: head_hit.is_some(),
+    }];
+    events.extend(checks.into_iter().map(|(kind, time)| SliderEvent {
+        kind,
+        time_ms: time as i32,
+        hit: is_tracking(
+            frames,
+            time as i32,
+            path.position_at(timing.progress_at(time)),
+            follow_radius,
+        ),
+    }));
+
+    let hit_count = events.iter().filter(|event| event.hit).count();
+    let result = if hit_count == events.len() {
+        HitResult::Hit300
+    } else if hit_count * 2 >= events.len() {
+        HitResult::Hit100
+    } else if hit_count > 0 {
+        HitResult::Hit50
+    } else {
+        HitResult::Miss
+    };
+
+    ObjectJudgement {
+        result,
+        time_ms: timing.end_ms(),
+        hit_offset: head_hit.map(|time| time - object.start_time.0),
+        slider_events: events,
+        spinner: None,
+    }
+}
+
+/// Spinners are judged on how much of the required spins were done by the end.
+fn judge_spinner(
+    od: f32,
+    clock_rate: f64,
+    object: &HitObject,
+    end_ms: i32,
+    frames: &[(i32, ReplayAction)],
+) -> ObjectJudgement {
+    let start_ms = object.start_time.0;
+    let required = required_spins(od, end_ms - start_ms);
+    let spins = SpinnerSpins::new(frames, start_ms, end_ms, clock_rate, required);
+    let progress = if required == 0 {
+        1.0
+    } else {
+        spins.spins_at(end_ms) / required as f32
+    };
+    let result = if progress >= 1.0 {
+        HitResult::Hit300
+    } else if progress > 0.9 {
+        HitResult::Hit100
+    } else if progress > 0.75 {
+        HitResult::Hit50
+    } else {
+        HitResult::Miss
+    };
+
+    ObjectJudgement {
+        result,
+        time_ms: end_ms,
+        hit_offset: None,
+        slider_events: Vec::new(),
+        spinner: Some(spins),
+    }
+}
+
+/// Judges every hit object against the replay, in beatmap order.
+pub fn judge(map_data: &BeatmapData, frames: &[(i32, ReplayAction)]) -> Vec<ObjectJudgement> {
+    let beatmap = &map_data.beatmap;
+    let windows = od_to_hit_windows(map_data.difficulty.overall_difficulty);
+    let window_50 = windows.2 as i32;
+    let radius = map_data.cs_osupixels;
+    let heads = judge_heads(&beatmap.hit_objects, frames, radius, window_50);
+
+    beatmap
+        .hit_objects
+        .iter()
+        .zip(heads)
+        .map(|(object, head_hit)| match &object.kind {
+            HitObjectKind::Circle => match head_hit {
+                Some(time) => ObjectJudgement {
+                    result: judge_offset(time - object.start_time.0, windows)
+                        .unwrap_or(HitResult::Miss),
+                    time_ms: time,
+                    hit_offset: Some(time - object.start_time.0),
+                    slider_events: Vec::new(),
+                    spinner: None,
+                },
+                None => ObjectJudgement {
+                    result: HitResult::Miss,
+                    time_ms: object.start_time.0 + window_50,
+                    hit_offset: None,
+                    slider_events: Vec::new(),
+                    spinner: None,
+                },
+            },
+            HitObjectKind::Slider(slider) => judge_slider(
+                beatmap,
+                object,
+                slider,
+                frames,
+                head_hit,
+                radius * FOLLOW_RADIUS_SCALE,
+            ),
+            HitObjectKind::Spinner(SpinnerInfo { end_time }) => judge_spinner(
+                map_data.difficulty.overall_difficulty,
+                map_data.clock_rate,
+                object,
+                end_time.0,
+                frames,
+            ),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+use crate::{sampler::frame, spinner::spin_frame};
+
+#[test]
+fn test_pressed_buttons() {
+    let k1 = Buttons::K1 | Buttons::M1;
+    let k2 = Buttons::K2 | Buttons::M2;
+    assert_eq!(pressed_buttons(Buttons::empty(), k1), Buttons::M1);
+    assert_eq!(pressed_buttons(k1, k1), Buttons::empty());
+    assert_eq!(pressed_buttons(k1, k1 | k2), Buttons::M2);
+    assert_eq!(pressed_buttons(k2, Buttons::empty()), Buttons::empty());
+}
+
+#[test]
+fn test_judge_offset() {
+    let windows = od_to_hit_windows(5.0);
+    assert_eq!(judge_offset(0, windows), Some(HitResult::Hit300));
+    assert_eq!(judge_offset(-50, windows), Some(HitResult::Hit300));
+    assert_eq!(judge_offset(80, windows), Some(HitResult::Hit100));
+    assert_eq!(judge_offset(-150, windows), Some(HitResult::Hit50));
+    assert_eq!(judge_offset(151, windows), None);
+}
+
+#[test]
+fn test_judge_heads_notelock() {
+    let circle = |time: i32, x: i32| HitObject {
+        pos: Point::new(x, 100),
+        start_time: TimestampMillis(time),
+        kind: HitObjectKind::Circle,
+        new_combo: false,
+        skip_color: 0,
+        additions: Additions::empty(),
+        sample_info: SampleInfo::default(),
+    };
+    let objects = vec![circle(1000, 100), circle(1100, 300)];
+    let k1 = Buttons::K1 | Buttons::M1;
+
+    // Clicking the second circle while the first one is still hittable is ignored
+    let frames = vec![
+        frame(1090, 300.0, 100.0, k1),
+        frame(1095, 300.0, 100.0, Buttons::empty()),
+        frame(1105, 300.0, 100.0, k1),
+    ];
+    assert_eq!(judge_heads(&objects, &frames, 30.0, 150), vec![None, None]);
+
+    // Once the first circle's window has passed the second one can be hit
+    let frames = vec![
+        frame(1000, 100.0, 100.0, k1),
+        frame(1010, 100.0, 100.0, Buttons::empty()),
+        frame(1120, 300.0, 100.0, k1),
+    ];
+    assert_eq!(
+        judge_heads(&objects, &frames, 30.0, 150),
+        vec![Some(1000), Some(1120)]
+    );
+
+    // Holding a key through the second circle doesn't hit it
+    let frames = vec![frame(1000, 100.0, 100.0, k1), frame(1100, 300.0, 100.0, k1)];
+    assert_eq!(
+        judge_heads(&objects, &frames, 30.0, 150),
+        vec![Some(1000), None]
+    );
+}
+
+#[test]
+fn test_judge_spinner() {
+    let spinner = HitObject {
+        pos: Point::new(256, 192),
+        start_time: TimestampMillis(0),
+        kind: HitObjectKind::Spinner(SpinnerInfo {
+            end_time: TimestampMillis(1000),
+        }),
+        new_combo: false,
+        skip_color: 0,
+        additions: Additions::empty(),
+        sample_info: SampleInfo::default(),
+    };
+    let k1 = Buttons::K1 | Buttons::M1;
+    // A quarter turn every 40ms, for 6.25 spins over the whole spinner
+    let spin = |until: i32| -> Vec<_> {
+        (0..=25)
+            .map(|i| {
+                let angle = (i * 40).min(until) as f32 / 160.0 * std::f32::consts::TAU;
+                spin_frame(i * 40, angle, k1)
+            })
+            .collect()
+    };
+
+    // OD 5 needs 5 spins a second
+    let judgement = judge_spinner(5.0, 1.0, &spinner, 1000, &spin(1000));
+    assert_eq!(judgement.result, HitResult::Hit300);
+    assert_eq!(judgement.time_ms, 1000);
+    let spinner_spins = judgement.spinner_spins();
+    assert_eq!(spinner_spins.len(), 6);
+    assert!(!spinner_spins[4].bonus);
+    assert!(spinner_spins[5].bonus);
+
+    assert_eq!(
+        judge_spinner(5.0, 1.0, &spinner, 1000, &spin(760)).result,
+        HitResult::Hit100
+    );
+    assert_eq!(
+        judge_spinner(5.0, 1.0, &spinner, 1000, &spin(640)).result,
+        HitResult::Hit50
+    );
+    assert_eq!(
+        judge_spinner(5.0, 1.0, &spinner, 1000, &[]).result,
+        HitResult::Miss
+    );
+}
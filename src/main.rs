@@ -1,73 +1,160 @@
 use ggez::conf::{WindowMode, WindowSetup};
 use helper::{ar_to_ms, cs_to_osupixels};
-use libosu::{beatmap::Beatmap, db::Db, replay::Replay};
+use libosu::{
+    beatmap::Beatmap,
+    db::Db,
+    prelude::{Difficulty, Mods},
+    replay::Replay,
+};
+use options::Options;
 use std::{
     io::BufReader,
     path::{Path, PathBuf},
 };
+use structopt::StructOpt;
 
+mod audio;
+mod combo;
 mod encoder;
 mod graphics;
 mod helper;
+mod judgement;
+mod mods;
+mod options;
 mod player;
+mod render;
+mod sampler;
+mod score;
+mod skin;
+mod slider;
+mod spinner;
 pub struct BeatmapData {
     pub beatmap: Beatmap,
+    /// The beatmap's difficulty settings with the replay's mods applied
+    pub difficulty: Difficulty,
+    pub mods: Mods,
+    /// Beatmap milliseconds per real millisecond
+    pub clock_rate: f64,
     pub ar_ms: i32,
     pub cs_osupixels: f32,
+    /// Combo colour and number of each hit object
+    pub combos: Vec<combo::ComboInfo>,
     pub folder: PathBuf,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let replay = Replay::parse(&mut BufReader::new(std::fs::File::open("replay.osr")?)).unwrap();
-
-    let map_data = {
-        let osudb = Db::parse(BufReader::new(
-            std::fs::File::open("C:\\Program Files\\osu!\\osu!.db").unwrap(),
-        ))
-        .unwrap();
+impl BeatmapData {
+    pub fn load(options: &Options, replay: &Replay) -> Result<Self, Box<dyn std::error::Error>> {
+        let osudb_path = options.osu_dir.join("osu!.db");
+        let osudb = Db::parse(BufReader::new(open_file(&osudb_path)?))
+            .map_err(|e| format!("Couldn't parse {}: {}", osudb_path.display(), e))?;
 
         let beatmap = osudb
             .beatmaps
             .into_iter()
             .find(|beatmap| beatmap.hash == replay.beatmap_hash)
-            .expect("Couldn't find replay beatmap in local beatmaps");
+            .ok_or("Couldn't find replay beatmap in local beatmaps")?;
 
-        let folder = Path::new("D:\\osu\\Songs").join(beatmap.folder_name);
+        let folder = options.songs_dir().join(beatmap.folder_name);
         let beatmap_file = folder.join(beatmap.beatmap_file_name);
 
-        let beatmap = libosu::beatmap::Beatmap::parse(&mut BufReader::new(std::fs::File::open(
-            beatmap_file,
-        )?))?;
+        let mut beatmap = Beatmap::parse(&mut BufReader::new(open_file(&beatmap_file)?))
+            .map_err(|e| format!("Couldn't parse {}: {}", beatmap_file.display(), e))?;
+
+        // ffmpeg would only notice once rendering has started
+        let audio_file = folder.join(&beatmap.audio_filename);
+        if !audio_file.is_file() {
+            return Err(format!("Beatmap song {} doesn't exist", audio_file.display()).into());
+        }
+
+        let mods = replay.mods;
+        if mods.contains(Mods::HardRock) {
+            mods::flip_vertically(&mut beatmap);
+        }
+        let difficulty = mods::apply_difficulty_mods(&beatmap.difficulty, mods);
 
-        BeatmapData {
-            ar_ms: ar_to_ms(beatmap.difficulty.approach_rate),
-            cs_osupixels: cs_to_osupixels(beatmap.difficulty.circle_size),
+        Ok(BeatmapData {
+            ar_ms: ar_to_ms(difficulty.approach_rate),
+            cs_osupixels: cs_to_osupixels(difficulty.circle_size),
+            clock_rate: mods::clock_rate(mods),
+            combos: combo::combo_infos(&beatmap.hit_objects),
+            difficulty,
+            mods,
             beatmap,
             folder,
-        }
-    };
-
-    let (mut ctx, event_loop) = ggez::ContextBuilder::new("osr2mp4-rs", "nobbele")
-        .window_mode(WindowMode {
-            width: 640.0,
-            height: 480.0,
-            resizable: false,
-            ..WindowMode::default()
         })
-        .window_setup(WindowSetup {
-            title: "osr2mp4-rs".to_owned(),
-            ..WindowSetup::default()
-        })
-        .add_resource_path("C:\\Program Files\\osu!")
-        .build()
-        .unwrap();
+    }
+}
+
+fn open_file(path: &Path) -> Result<std::fs::File, String> {
+    std::fs::File::open(path).map_err(|e| format!("Couldn't open {}: {}", path.display(), e))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = Options::from_args();
+
+    if !options.osu_dir.is_dir() {
+        return Err(format!("osu! directory {} doesn't exist", options.osu_dir.display()).into());
+    }
+    if !options.songs_dir().is_dir() {
+        return Err(format!(
+            "Songs directory {} doesn't exist",
+            options.songs_dir().display()
+        )
+        .into());
+    }
+    if let Some(skin_dir) = options.skin_dir() {
+        if !skin_dir.is_dir() {
+            return Err(format!("Skin directory {} doesn't exist", skin_dir.display()).into());
+        }
+    }
+    if options.fps == 0 {
+        return Err("Framerate must be greater than 0".into());
+    }
+    if !(0.0..=1.0).contains(&options.music_volume) {
+        return Err("Music volume must be between 0.0 and 1.0".into());
+    }
+    if !(0.0..=1.0).contains(&options.effects_volume) {
+        return Err("Effects volume must be between 0.0 and 1.0".into());
+    }
+    if let (Some(start), Some(end)) = (options.start, options.end) {
+        if end <= start {
+            return Err(format!("End time {}ms must be after start time {}ms", end, start).into());
+        }
+    }
+
+    let replay = Replay::parse(&mut BufReader::new(open_file(&options.replay)?))
+        .map_err(|e| format!("Couldn't parse {}: {}", options.replay.display(), e))?;
+
+    let map_data = BeatmapData::load(&options, &replay)?;
 
     println!(
         "Running a replay of {} playing {} [{}]",
         replay.player_username, map_data.beatmap.title, map_data.beatmap.difficulty_name
     );
 
-    let player = player::Player::new(&mut ctx, replay, map_data, 30);
+    let mut player = player::Player::new(replay, map_data, options.clone())?;
+    for mismatch in player.verify_score() {
+        eprintln!("Score doesn't match the replay, {}", mismatch);
+    }
 
-    ggez::event::run(ctx, event_loop, player)
+    if options.preview {
+        let (mut ctx, event_loop) = ggez::ContextBuilder::new("osr2mp4-rs", "nobbele")
+            .window_mode(WindowMode {
+                width: options.width() as f32,
+                height: options.height() as f32,
+                resizable: false,
+                ..WindowMode::default()
+            })
+            .window_setup(WindowSetup {
+                title: "osr2mp4-rs".to_owned(),
+                ..WindowSetup::default()
+            })
+            .build()?;
+        player.play_music(&mut ctx)?;
+        ggez::event::run(ctx, event_loop, player)
+    } else {
+        let output = render::render(&mut player, &options)?;
+        println!("Rendered replay to {}", output.display());
+        Ok(())
+    }
 }
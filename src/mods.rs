@@ -0,0 +1,80 @@
+use libosu::prelude::*;
+
+use crate::graphics::playfield::PLAYFIELD_HEIGHT;
+
+/// How fast the beatmap plays relative to real time.
+pub fn clock_rate(mods: Mods) -> f64 {
+    if mods.intersects(Mods::DoubleTime | Mods::Nightcore) {
+        1.5
+    } else if mods.contains(Mods::HalfTime) {
+        0.75
+    } else {
+        1.0
+    }
+}
+
+/// Whether speed changes also change the pitch of the song.
+pub fn changes_pitch(mods: Mods) -> bool {
+    mods.contains(Mods::Nightcore)
+}
+
+/// Difficulty settings with HardRock and Easy applied.
+/// Speed changing mods don't touch these, they act on the timeline instead.
+pub fn apply_difficulty_mods(difficulty: &Difficulty, mods: Mods) -> Difficulty {
+    let mut difficulty = difficulty.clone();
+    if mods.contains(Mods::HardRock) {
+        difficulty.circle_size = (difficulty.circle_size * 1.3).min(10.0);
+        difficulty.approach_rate = (difficulty.approach_rate * 1.4).min(10.0);
+        difficulty.overall_difficulty = (difficulty.overall_difficulty * 1.4).min(10.0);
+        difficulty.hp_drain_rate = (difficulty.hp_drain_rate * 1.4).min(10.0);
+    } else if mods.contains(Mods::Easy) {
+        difficulty.circle_size *= 0.5;
+        difficulty.approach_rate *= 0.5;
+        difficulty.overall_difficulty *= 0.5;
+        difficulty.hp_drain_rate *= 0.5;
+    }
+    difficulty
+}
+
+/// Mirrors every hit object upside down, like HardRock does.
+pub fn flip_vertically(beatmap: &mut Beatmap) {
+    let flip = |y: i32| PLAYFIELD_HEIGHT as i32 - y;
+    for object in &mut beatmap.hit_objects {
+        object.pos.y = flip(object.pos.y);
+        if let HitObjectKind::Slider(slider) = &mut object.kind {
+            for point in &mut slider.control_points {
+                point.y = flip(point.y);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_clock_rate() {
+    assert_eq!(clock_rate(Mods::empty()), 1.0);
+    assert_eq!(clock_rate(Mods::DoubleTime | Mods::Hidden), 1.5);
+    assert_eq!(clock_rate(Mods::DoubleTime | Mods::Nightcore), 1.5);
+    assert_eq!(clock_rate(Mods::HalfTime), 0.75);
+}
+
+#[test]
+fn test_apply_difficulty_mods() {
+    let difficulty = Difficulty {
+        hp_drain_rate: 5.0,
+        circle_size: 4.0,
+        overall_difficulty: 8.0,
+        approach_rate: 9.0,
+        ..Difficulty::default()
+    };
+    let hard_rock = apply_difficulty_mods(&difficulty, Mods::HardRock);
+    assert_eq!(hard_rock.circle_size, 5.2);
+    assert_eq!(hard_rock.approach_rate, 10.0);
+    assert_eq!(hard_rock.overall_difficulty, 10.0);
+    assert_eq!(hard_rock.hp_drain_rate, 7.0);
+
+    let easy = apply_difficulty_mods(&difficulty, Mods::Easy);
+    assert_eq!(easy.circle_size, 2.0);
+    assert_eq!(easy.approach_rate, 4.5);
+    assert_eq!(easy.overall_difficulty, 4.0);
+    assert_eq!(easy.hp_drain_rate, 2.5);
+}
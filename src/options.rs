@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use crate::{graphics::slider::Snaking, sampler::Interpolation};
+
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(name = "osr2mp4-rs", about = "Renders osu! replays to video")]
+pub struct Options {
+    /// Replay file to render
+    #[structopt(parse(from_os_str))]
+    pub replay: PathBuf,
+
+    /// osu! install directory, containing osu!.db and the Skins folder
+    #[structopt(long, parse(from_os_str))]
+    pub osu_dir: PathBuf,
+
+    /// Songs directory, defaults to the Songs folder inside the osu! directory
+    #[structopt(long, parse(from_os_str))]
+    pub songs_dir: Option<PathBuf>,
+
+    /// Skin folder, or the name of one inside <osu-dir>/Skins. Defaults to the built-in skin
+    #[structopt(long)]
+    pub skin: Option<String>,
+
+    /// Output video file
+    #[structopt(short, long, parse(from_os_str), default_value = "out.mp4")]
+    pub output: PathBuf,
+
+    /// Output resolution as WIDTHxHEIGHT
+    #[structopt(short, long, default_value = "640x480", parse(try_from_str = parse_resolution))]
+    pub resolution: (u16, u16),
+
+    #[structopt(long, default_value = "60")]
+    pub fps: u16,
+
+    /// Number of cursor trail images drawn behind the cursor, 0 to disable the trail
+    #[structopt(long, default_value = "8")]
+    pub cursor_trail: usize,
+
+    /// Grow slider bodies out of their head as they appear
+    #[structopt(long)]
+    pub snaking_in: bool,
+
+    /// Shrink slider bodies behind the ball as it goes over them the last time
+    #[structopt(long)]
+    pub snaking_out: bool,
+
+    /// How the cursor moves between replay frames: linear or spline
+    #[structopt(long, default_value = "linear")]
+    pub cursor_interpolation: Interpolation,
+
+    /// Time in the beatmap to start rendering from, in milliseconds. Defaults to the start of the lead-in
+    #[structopt(long, allow_hyphen_values = true)]
+    pub start: Option<i32>,
+
+    /// Time in the beatmap to stop rendering at, in milliseconds. Defaults to the end of the replay
+    #[structopt(long, allow_hyphen_values = true)]
+    pub end: Option<i32>,
+
+    /// Volume of the song, from 0.0 to 1.0
+    #[structopt(long, default_value = "1.0")]
+    pub music_volume: f32,
+
+    /// Volume of the hitsounds, from 0.0 to 1.0
+    #[structopt(long, default_value = "1.0")]
+    pub effects_volume: f32,
+
+    /// Play the replay back live in a window instead of rendering it to the output file
+    #[structopt(long)]
+    pub preview: bool,
+}
+
+impl Options {
+    pub fn skin_dir(&self) -> Option<PathBuf> {
+        self.skin.as_ref().map(|skin| {
+            let path = PathBuf::from(skin);
+            if path.is_dir() {
+                path
+            } else {
+                self.osu_dir.join("Skins").join(skin)
+            }
+        })
+    }
+
+    pub fn songs_dir(&self) -> PathBuf {
+        self.songs_dir
+            .clone()
+            .unwrap_or_else(|| self.osu_dir.join("Songs"))
+    }
+
+    pub fn snaking(&self) -> Snaking {
+        Snaking {
+            snake_in: self.snaking_in,
+            snake_out: self.snaking_out,
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.resolution.0
+    }
+
+    pub fn height(&self) -> u16 {
+        self.resolution.1
+    }
+}
+
+fn parse_resolution(s: &str) -> Result<(u16, u16), String> {
+    let (width, height) = {
+        let mut split = s.splitn(2, |c| c == 'x' || c == 'X');
+        (split.next(), split.next())
+    };
+    match (width, height) {
+        (Some(width), Some(height)) => {
+            let width = width
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid width '{}': {}", width, e))?;
+            let height = height
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid height '{}': {}", height, e))?;
+            if width == 0 || height == 0 {
+                return Err(format!("Resolution '{}' must not be empty", s));
+            }
+            Ok((width, height))
+        }
+        _ => Err(format!("Expected resolution as WIDTHxHEIGHT, got '{}'", s)),
+    }
+}
+
+#[test]
+fn test_parse_resolution() {
+    assert_eq!(parse_resolution("1920x1080"), Ok((1920, 1080)));
+    assert_eq!(parse_resolution("640X480"), Ok((640, 480)));
+    assert!(parse_resolution("1920").is_err());
+    assert!(parse_resolution("0x1080").is_err());
+    assert!(parse_resolution("axb").is_err());
+}
@@ -1,340 +1,450 @@
-use std::iter::Peekable;
+use std::{path::PathBuf, rc::Rc};
 
 use ggez::{
     audio::SoundSource,
     event::{quit, EventHandler},
-    graphics::{Canvas, Color, DrawMode, DrawParam, Drawable, FillOptions, Image, Rect},
-    mint, Context,
+    graphics::Color,
+    Context, GameError, GameResult,
 };
-use libosu::{prelude::*, replay::ReplayAction};
+use libosu::prelude::*;
 
 use crate::{
-    encoder::Encoder,
-    graphics::{circle::draw_circle, slider::draw_slider, spinner::draw_spinner},
+    graphics::{
+        backend::{Backend, SpriteParams, Texture},
+        circle::{circle_end_ms, draw_circle},
+        cursor::Cursor,
+        flashlight::Flashlight,
+        ggez_backend::GgezBackend,
+        hit_burst::draw_hit_bursts,
+        key_overlay::KeyOverlay,
+        playfield::Playfield,
+        slider::{draw_slider, SliderBodies},
+        spinner::draw_spinner,
+    },
+    helper::od_to_hit_windows,
+    judgement::{judge, ObjectJudgement},
+    options::Options,
+    sampler::{ReplaySample, ReplaySampler},
+    score::{self, ScoreTimeline},
+    skin::Skin,
+    slider::SliderTiming,
     BeatmapData,
 };
 
 pub struct Player {
     current_ms: i32,
-    current_action_ms: i32,
-    current_action: ReplayAction,
-    replay_data_iter: Peekable<std::vec::IntoIter<ReplayAction>>,
-    combo_color_index: usize,
-    combo_index: u8,
-    prev_obj_time: TimestampMillis,
-
-    fps: i32,
+    current_frame: ReplaySample,
+    sampler: ReplaySampler,
+    judgements: Vec<ObjectJudgement>,
+    score: ScoreTimeline,
+    start_ms: i32,
+    combo_colors: Vec<Color>,
+
+    options: Options,
+    skin: Skin,
+    elapsed_ms: f64,
     paused: bool,
 
-    encoder: Option<Encoder>,
-    //canvas: Canvas,
     replay: Replay,
-    background: Image,
-    music: ggez::audio::Source,
+    background: Option<Rc<Texture>>,
+    cursor: Cursor,
+    slider_bodies: SliderBodies,
+    key_overlay: KeyOverlay,
+    flashlight: Option<Flashlight>,
+    music: Option<ggez::audio::Source>,
+    /// Whether the music has started, which waits for the song's beginning on lead-in maps
+    music_started: bool,
     map_data: BeatmapData,
 }
 
 impl Player {
-    pub fn new(ctx: &mut Context, replay: Replay, map_data: BeatmapData, fps: i32) -> Self {
-        let mut iter = replay
+    pub fn new(replay: Replay, map_data: BeatmapData, options: Options) -> GameResult<Self> {
+        let frames = replay
             .parse_action_data()
-            .expect("Unable to parse replay")
-            .frames
-            .into_iter()
-            .peekable();
-        Self {
-            current_ms: 0,
-            current_action_ms: 0,
-            current_action: iter.next().expect("Replay is empty"),
-            replay_data_iter: iter,
-            combo_color_index: 0,
-            combo_index: 0,
-            prev_obj_time: TimestampMillis(0),
-
-            fps,
+            .map_err(|e| GameError::ResourceLoadError(format!("Unable to parse replay: {}", e)))?
+            .frames;
+        let sampler = ReplaySampler::new(&frames, options.cursor_interpolation);
+        if sampler.frames().is_empty() {
+            return Err(GameError::ResourceLoadError("Replay is empty".to_owned()));
+        }
+        let judgements = judge(&map_data, sampler.frames());
+        let key_overlay = KeyOverlay::new(sampler.frames());
+        let score = ScoreTimeline::new(&map_data, &judgements);
+
+        let background = match map_data.beatmap.events.iter().find_map(|item| match item {
+            Event::Background(e) => Some(map_data.folder.join(&e.filename)),
+            _ => None,
+        }) {
+            Some(path) => {
+                let bytes = std::fs::read(&path).map_err(|e| {
+                    GameError::ResourceLoadError(format!(
+                        "Couldn't read beatmap background image {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+                Some(Texture::from_bytes(&bytes)?)
+            }
+            None => None,
+        };
+
+        let skin = Skin::load(options.skin_dir().as_deref());
+        let beatmap_colors: Vec<_> = map_data
+            .beatmap
+            .colors
+            .iter()
+            .map(|color| Color::from_rgb(color.red, color.green, color.blue))
+            .collect();
+        let combo_colors = skin.combo_colors(&beatmap_colors);
+
+        let start_ms = options
+            .start
+            .unwrap_or_else(|| -(map_data.beatmap.audio_leadin as i32));
+
+        Ok(Self {
+            current_ms: start_ms,
+            current_frame: sampler.sample(start_ms),
+            sampler,
+            judgements,
+            score,
+            start_ms,
+            combo_colors,
+
+            skin,
+            cursor: Cursor::new(options.cursor_trail),
+            slider_bodies: SliderBodies::new(),
+            key_overlay,
+            options,
+            elapsed_ms: 0.0,
             paused: false,
 
-            encoder: None,
-            //canvas: ggez::graphics::Canvas::with_window_size(ctx).unwrap(),
             replay,
-            background: Image::from_bytes(
-                ctx,
-                &std::fs::read(
-                    map_data
-                        .beatmap
-                        .events
-                        .iter()
-                        .find_map(|item| match item {
-                            Event::Background(e) => Some(&e.filename),
-                            _ => None,
-                        })
-                        .map(|s| map_data.folder.join(s))
-                        .expect("No background image"),
-                )
-                .expect("Couldn't find beatmap background image"),
-            )
-            .expect("Couldn't load beatmap background image"),
-            music: {
-                let mut source = ggez::audio::Source::from_data(
-                    ctx,
-                    ggez::audio::SoundData::from_bytes(
-                        &std::fs::read(map_data.folder.join(&map_data.beatmap.audio_filename))
-                            .expect("Couldn't find beatmap music file"),
-                    ),
-                )
-                .expect("Couldn't load beatmap music file");
-                source.set_volume(0.1);
-                source.set_query_interval(std::time::Duration::from_millis(1000 / 60));
-                source.play(ctx).expect("Could not start music");
-                source
+            background,
+            flashlight: if map_data.mods.contains(Mods::Flashlight) {
+                Some(Flashlight::new())
+            } else {
+                None
             },
+            music: None,
+            music_started: false,
             map_data,
-        }
+        })
     }
-}
 
-impl EventHandler for Player {
-    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
-        if let Some(encoder) = self.encoder.take() {
-            encoder.finish();
+    /// Loads the music for live previews, which starts playing once the song begins.
+    pub fn play_music(&mut self, ctx: &mut Context) -> GameResult {
+        // ggez can't seek audio sources, so previews starting partway into the song are played without music
+        if self.options.start.unwrap_or(0) > 0 {
+            return Ok(());
         }
-        false // false means quit
+        let path = self.audio_path();
+        let bytes = std::fs::read(&path).map_err(|e| {
+            GameError::ResourceLoadError(format!(
+                "Couldn't read beatmap music file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let mut source =
+            ggez::audio::Source::from_data(ctx, ggez::audio::SoundData::from_bytes(&bytes))?;
+        source.set_volume(self.options.music_volume);
+        // Live playback can't time stretch, so DoubleTime and HalfTime change pitch like Nightcore
+        source.set_pitch(self.map_data.clock_rate as f32);
+        source.set_query_interval(std::time::Duration::from_millis(1000 / 60));
+        self.music = Some(source);
+        Ok(())
     }
 
-    fn update(&mut self, _ctx: &mut ggez::Context) -> ggez::GameResult {
-        if !self.paused {
-            self.current_ms = 29216 - self.music.elapsed().as_millis() as i32;
-        }
+    /// Time in the beatmap the replay is played back from, in milliseconds.
+    /// Negative when the beatmap has a lead-in.
+    pub fn start_ms(&self) -> i32 {
+        self.start_ms
+    }
 
-        Ok(())
+    /// Path of the beatmap's song.
+    pub fn audio_path(&self) -> PathBuf {
+        self.map_data
+            .folder
+            .join(&self.map_data.beatmap.audio_filename)
     }
 
-    fn key_down_event(
-        &mut self,
-        _ctx: &mut Context,
-        keycode: ggez::event::KeyCode,
-        _keymods: ggez::event::KeyMods,
-        _repeat: bool,
-    ) {
-        if keycode == ggez::event::KeyCode::Space {
-            self.paused = !self.paused;
-            if self.music.paused() {
-                self.music.resume();
-            } else {
-                self.music.pause();
-            }
-        }
+    pub fn map_data(&self) -> &BeatmapData {
+        &self.map_data
     }
 
-    fn draw(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
-        println!("{}ms -> {:?}", self.current_ms, self.current_action);
-
-        //ggez::graphics::set_canvas(ctx, Some(&self.canvas));
-        ggez::graphics::clear(
-            ctx,
-            Color {
-                r: 0.3,
-                g: 0.4,
-                b: 0.5,
-                a: 1.0,
-            },
-        );
+    pub fn skin(&self) -> &Skin {
+        &self.skin
+    }
 
-        self.background.draw(
-            ctx,
-            DrawParam::new().scale(
-                glam::vec2(
-                    ggez::graphics::drawable_size(ctx).0,
-                    ggez::graphics::drawable_size(ctx).1,
-                ) / glam::vec2(
-                    self.background.dimensions().w,
-                    self.background.dimensions().h,
-                ),
-            ),
-        )?;
+    /// Judgement of every hit object, in beatmap order.
+    pub fn judgements(&self) -> &[ObjectJudgement] {
+        &self.judgements
+    }
+
+    /// Differences between the simulated final score and the one the replay was saved with.
+    pub fn verify_score(&self) -> Vec<String> {
+        score::verify(&self.score.last(), &self.replay)
+    }
 
-        let mut active_object_iter = {
+    /// Time of the last meaningful replay frame, in milliseconds.
+    pub fn replay_end_ms(&self) -> i32 {
+        self.sampler.end_ms()
+    }
+
+    /// Moves the player to `current_ms`.
+    /// Returns true once the replay has run out of frames.
+    pub fn seek(&mut self, current_ms: i32) -> bool {
+        self.current_ms = current_ms;
+        self.current_frame = self.sampler.sample(current_ms);
+        current_ms >= self.sampler.end_ms()
+    }
+
+    /// Whether the player is holding a slider they hit the head of at the current time.
+    fn is_holding_slider(&self) -> bool {
+        let held = self
+            .current_frame
+            .buttons
+            .intersects(Buttons::M1 | Buttons::M2);
+        held && self
+            .map_data
+            .beatmap
+            .hit_objects
+            .iter()
+            .zip(&self.judgements)
+            .any(|(object, judgement)| {
+                matches!(object.kind, HitObjectKind::Slider(..))
+                    && judgement.hit_offset.is_some()
+                    && object.start_time.0 <= self.current_ms
+                    && self.current_ms < judgement.time_ms
+            })
+    }
+
+    /// Draws the current frame to the active render target without presenting it.
+    pub fn draw_frame(&mut self, backend: &mut dyn Backend) -> GameResult {
+        backend.clear(Color {
+            r: 0.3,
+            g: 0.4,
+            b: 0.5,
+            a: 1.0,
+        });
+
+        if let Some(background) = &self.background {
+            let scale = backend.size() / background.size();
+            backend.draw_sprite(background, SpriteParams::new().scale(scale))?;
+        }
+
+        let active_objects = {
             let current_ms = self.current_ms;
             let ar_ms = self.map_data.ar_ms;
             let beatmap = &self.map_data.beatmap;
-            let iter = self
-                .map_data
+            let window_50 = od_to_hit_windows(self.map_data.difficulty.overall_difficulty).2 as i32;
+            self.map_data
                 .beatmap
                 .hit_objects
                 .iter()
-                .filter(move |&obj| {
+                .enumerate()
+                .zip(&self.judgements)
+                .zip(&self.map_data.combos)
+                .filter(move |&(((_, obj), judgement), _)| {
                     current_ms >= obj.start_time.0 - ar_ms
                         && match &obj.kind {
-                            HitObjectKind::Circle => current_ms < obj.start_time.0,
-                            HitObjectKind::Slider(..) => {
-                                let duration = beatmap
-                                    .get_slider_duration(obj)
-                                    .expect("Expected slider duration");
-                                current_ms < obj.start_time.0 + duration as i32
+                            HitObjectKind::Circle => {
+                                current_ms
+                                    < circle_end_ms(
+                                        obj.start_time.0,
+                                        judgement.hit_ms(obj),
+                                        window_50,
+                                    )
+                            }
+                            HitObjectKind::Slider(info) => {
+                                current_ms < SliderTiming::new(beatmap, obj, info).end_ms()
                             }
                             HitObjectKind::Spinner(SpinnerInfo { end_time }) => {
                                 current_ms < end_time.0
                             }
                         }
                 })
-                .peekable();
-            iter
         };
 
-        if let Some(first_obj) = active_object_iter.peek() {
-            if first_obj.start_time != self.prev_obj_time {
-                self.combo_index += 1;
-                if first_obj.new_combo {
-                    self.combo_color_index =
-                        (self.combo_color_index + 1) % self.map_data.beatmap.colors.len();
-                    self.combo_index = 0;
-                }
-            }
-            self.prev_obj_time = first_obj.start_time;
-        }
-
-        let mut active_combo_color_index = self.combo_color_index;
-        let mut active_combo_index = self.combo_index;
-        for (i, object) in active_object_iter.enumerate() {
-            if object.new_combo && i != 0 {
-                active_combo_color_index =
-                    (active_combo_color_index + 1) % self.map_data.beatmap.colors.len();
-                active_combo_index = 0;
-            }
-            active_combo_index += 1;
-            let color = self.map_data.beatmap.colors[active_combo_color_index];
-            let color = Color::from_rgb(color.red, color.green, color.blue);
+        let playfield = Playfield::new(backend.size());
+        for (((index, object), judgement), combo) in active_objects {
+            let color = combo.color(&self.combo_colors);
             match &object.kind {
                 HitObjectKind::Circle => draw_circle(
-                    ctx,
+                    backend,
+                    &playfield,
                     &self.map_data,
+                    &self.skin,
                     self.current_ms,
                     object,
                     color,
-                    active_combo_index,
+                    combo.number,
+                    judgement.hit_ms(object),
                 ),
                 HitObjectKind::Slider(info) => draw_slider(
-                    ctx,
+                    backend,
+                    &playfield,
                     &self.map_data,
+                    &self.skin,
+                    &mut self.slider_bodies,
+                    index,
                     self.current_ms,
                     object,
                     info,
                     color,
-                    active_combo_index,
+                    combo.number,
+                    judgement,
+                    &self.current_frame,
+                    self.options.snaking(),
                 ),
-                HitObjectKind::Spinner(..) => {
-                    draw_spinner(ctx, &self.map_data, self.current_ms, &object)
+                HitObjectKind::Spinner(SpinnerInfo { end_time }) => {
+                    // Spins are worked out once when judging, every spinner has them
+                    let spins = judgement.spinner.as_ref().ok_or_else(|| {
+                        GameError::RenderError("Spinner was judged without its spins".to_owned())
+                    })?;
+                    draw_spinner(
+                        backend,
+                        &playfield,
+                        &self.map_data,
+                        &self.skin,
+                        self.current_ms,
+                        object,
+                        end_time.0,
+                        spins,
+                    )
                 }
-            }
+            }?;
         }
+        self.slider_bodies.evict(self.current_ms);
 
-        ggez::graphics::Text::new(format!(
-            "{} playing {} - {} [{}]",
-            self.replay.player_username,
-            self.map_data.beatmap.artist_unicode,
-            self.map_data.beatmap.title_unicode,
-            self.map_data.beatmap.difficulty_name
-        ))
-        .draw(ctx, DrawParam::new().dest(mint::Point2 { x: 0.0, y: 16.0 }))
-        .unwrap();
-
-        ggez::graphics::Mesh::new_rectangle(
-            ctx,
-            DrawMode::Fill(FillOptions::DEFAULT),
-            Rect {
-                x: 0.0,
-                y: 0.0,
-                w: 10.0,
-                h: 10.0,
-            },
-            Color {
-                r: 1.0,
-                g: 1.0,
-                b: 1.0,
-                a: 1.0,
-            },
-        )
-        .unwrap()
-        .draw(
-            ctx,
-            DrawParam::new().dest(mint::Point2 {
-                x: self.current_action.x,
-                y: self.current_action.y,
-            }),
-        )
-        .unwrap();
-
-        for &button in [
-            Buttons::K1,
-            Buttons::K2,
-            Buttons::M1,
-            Buttons::M2,
-            Buttons::SMOKE,
-        ]
-        .iter()
-        .filter(|&&b| self.current_action.buttons.contains(b))
-        {
-            ggez::graphics::Mesh::new_rectangle(
-                ctx,
-                DrawMode::Fill(FillOptions::DEFAULT),
-                Rect {
-                    x: 0.0,
-                    y: 0.0,
-                    w: 10.0,
-                    h: 10.0,
-                },
-                Color {
-                    r: 1.0,
-                    g: 0.0,
-                    b: 0.0,
-                    a: 1.0,
-                },
-            )
-            .unwrap()
-            .draw(
-                ctx,
-                DrawParam::new().dest(mint::Point2 {
-                    x: match button {
-                        Buttons::K1 => 0.0,
-                        Buttons::K2 => 10.0,
-                        //Buttons::M1 => 20.0, mouse buttons are broken for some reason
-                        //Buttons::M2 => 30.0,
-                        Buttons::SMOKE => 40.0,
-                        _ => -10.0,
-                    },
-                    y: 0.0,
-                }),
-            )
-            .unwrap();
+        draw_hit_bursts(
+            backend,
+            &playfield,
+            &self.map_data,
+            &self.skin,
+            self.current_ms,
+            &self.judgements,
+        )?;
+
+        let state = self.score.at(self.current_ms);
+        if let Some(flashlight) = &mut self.flashlight {
+            flashlight.update(self.current_ms, self.current_frame.position);
+        }
+        if let Some(flashlight) = &self.flashlight {
+            flashlight.draw(backend, &playfield, state.combo, self.is_holding_slider())?;
         }
 
-        ggez::graphics::present(ctx).unwrap();
-
-        /*if let Some(encoder) = &mut self.encoder {
-            encoder
-                .encode(&self.canvas.image().to_rgba8(ctx).unwrap());
-        }*/
-
-        loop {
-            if let Some(next) = self.replay_data_iter.peek() {
-                if self.current_ms >= self.current_action_ms as i32 + next.time as i32 {
-                    // Guaranteed to work since we successfully peeked, same as `next`
-                    self.current_action = self.replay_data_iter.next().unwrap();
-                    self.current_action_ms += self.current_action.time as i32;
-                    // Break if this is the last one as it's just garbage data
-                    if self.replay_data_iter.peek().is_none() {
-                        quit(ctx);
-                        break;
-                    }
-                } else {
-                    break;
+        backend.draw_text(
+            &format!(
+                "{} playing {} - {} [{}]",
+                self.replay.player_username,
+                self.map_data.beatmap.artist_unicode,
+                self.map_data.beatmap.title_unicode,
+                self.map_data.beatmap.difficulty_name
+            ),
+            glam::vec2(0.0, 16.0),
+            16.0,
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        )?;
+
+        let size = backend.size();
+        let score_text = format!("{:08}", state.score);
+        let accuracy_text = format!("{:.2}%", state.accuracy() * 100.0);
+        backend.draw_text(
+            &score_text,
+            glam::vec2(size.x - score_text.len() as f32 * 24.0, 0.0),
+            24.0,
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        )?;
+        backend.draw_text(
+            &accuracy_text,
+            glam::vec2(size.x - accuracy_text.len() as f32 * 16.0, 24.0),
+            16.0,
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        )?;
+        backend.draw_text(
+            &format!("{}x", state.combo),
+            glam::vec2(0.0, size.y - 24.0),
+            24.0,
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        )?;
+
+        self.cursor.update(
+            self.current_ms,
+            self.current_frame
+                .buttons
+                .intersects(Buttons::M1 | Buttons::M2),
+        );
+        self.cursor.draw(
+            backend,
+            &playfield,
+            &self.skin,
+            &self.sampler,
+            self.current_ms,
+        )?;
+
+        self.key_overlay
+            .draw(backend, &playfield, &self.skin, self.current_ms)?;
+
+        Ok(())
+    }
+}
+
+impl EventHandler for Player {
+    fn update(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        if !self.paused {
+            self.elapsed_ms = match &self.music {
+                // The song started once playback got to 0ms
+                Some(music) if self.music_started => {
+                    (-self.start_ms).max(0) as f64
+                        + music.elapsed().as_secs_f64() * 1000.0 * self.map_data.clock_rate
                 }
-            } else {
+                _ => {
+                    self.elapsed_ms
+                        + ggez::timer::delta(ctx).as_secs_f64() * 1000.0 * self.map_data.clock_rate
+                }
+            };
+            if self.seek(self.start_ms + self.elapsed_ms as i32) {
+                quit(ctx);
+            }
+            if let Some(music) = &mut self.music {
+                if !self.music_started && self.current_ms >= 0 {
+                    music.play(ctx)?;
+                    self.music_started = true;
+                }
+            }
+        }
+
+        if let Some(end) = self.options.end {
+            if self.current_ms >= end {
                 quit(ctx);
-                break;
             }
         }
 
         Ok(())
     }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: ggez::event::KeyCode,
+        _keymods: ggez::event::KeyMods,
+        _repeat: bool,
+    ) {
+        if keycode == ggez::event::KeyCode::Space {
+            self.paused = !self.paused;
+            if let (Some(music), true) = (&mut self.music, self.music_started) {
+                if music.paused() {
+                    music.resume();
+                } else {
+                    music.pause();
+                }
+            }
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context) -> ggez::GameResult {
+        self.draw_frame(&mut GgezBackend::new(ctx))?;
+        ggez::graphics::present(ctx)
+    }
 }
@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+
+use ggez::GameResult;
+
+use crate::{
+    audio::{mix_hitsounds, SampleLibrary},
+    encoder::{AudioTrack, Encoder},
+    graphics::cpu::CpuBackend,
+    mods::changes_pitch,
+    options::Options,
+    player::Player,
+};
+
+/// Number of frames needed to cover beatmap time `start_ms..end_ms` at `fps`,
+/// with the beatmap playing `clock_rate` times faster than real time.
+pub fn frame_count(start_ms: i32, end_ms: i32, fps: u16, clock_rate: f64) -> u64 {
+    ((end_ms - start_ms).max(0) as f64 / clock_rate * fps as f64 / 1000.0) as u64
+}
+
+/// Beatmap time of frame `frame` in milliseconds. Computed from the frame index so rounding never accumulates.
+pub fn frame_time(start_ms: i32, frame: u64, fps: u16, clock_rate: f64) -> i32 {
+    start_ms + (frame as f64 * 1000.0 / fps as f64 * clock_rate) as i32
+}
+
+/// A temporary file that is deleted when this goes out of scope, even if rendering fails partway through.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // The file may never have been written
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Renders the replay frame-by-frame on the CPU and encodes every frame,
+/// independent of how fast the machine can render and without needing a GPU.
+pub fn render(player: &mut Player, options: &Options) -> GameResult<PathBuf> {
+    let start_ms = player.start_ms();
+    let end_ms = options.end.unwrap_or_else(|| player.replay_end_ms());
+    let clock_rate = player.map_data().clock_rate;
+    let frames = frame_count(start_ms, end_ms, options.fps, clock_rate);
+
+    println!("Mixing hitsounds");
+    // Declared before the encoder so the encoder is done with it by the time it's deleted
+    let hitsounds = TempFile(
+        std::env::temp_dir().join(format!("osr2mp4-hitsounds-{}.wav", std::process::id())),
+    );
+    {
+        let map_data = player.map_data();
+        let mut samples = SampleLibrary::new(
+            map_data.folder.clone(),
+            player.skin().dir().map(Path::to_path_buf),
+        );
+        mix_hitsounds(
+            map_data,
+            &mut samples,
+            player.judgements(),
+            start_ms,
+            end_ms,
+        )
+        .write_wav(&hitsounds.0)?;
+    }
+
+    let audio = [
+        AudioTrack::new(player.audio_path(), start_ms, end_ms, options.music_volume)
+            .with_clock_rate(clock_rate, changes_pitch(player.map_data().mods)),
+        // Already mixed in real time
+        AudioTrack::new(
+            hitsounds.0.clone(),
+            0,
+            ((end_ms - start_ms) as f64 / clock_rate) as i32,
+            options.effects_volume,
+        ),
+    ];
+
+    let mut backend = CpuBackend::new(options.width(), options.height());
+    let mut encoder = Encoder::new(
+        &options.output,
+        options.width(),
+        options.height(),
+        options.fps,
+        &audio,
+    )?;
+
+    for frame in 0..frames {
+        player.seek(frame_time(start_ms, frame, options.fps, clock_rate));
+
+        player.draw_frame(&mut backend)?;
+        encoder.encode(backend.data())?;
+
+        if frame % options.fps as u64 == 0 {
+            println!("Rendered frame {}/{}", frame, frames);
+        }
+    }
+
+    Ok(encoder.finish()?)
+}
+
+#[test]
+fn test_frame_count() {
+    assert_eq!(frame_count(0, 180_000, 60, 1.0), 180 * 60);
+    assert_eq!(frame_count(0, 180_000, 30, 1.0), 180 * 30);
+    assert_eq!(frame_count(1000, 2000, 60, 1.0), 60);
+    assert_eq!(frame_count(2000, 1000, 60, 1.0), 0);
+    assert_eq!(frame_count(0, 180_000, 60, 1.5), 120 * 60);
+    assert_eq!(frame_count(0, 180_000, 60, 0.75), 240 * 60);
+}
+
+#[test]
+fn test_frame_time() {
+    assert_eq!(frame_time(0, 0, 60, 1.0), 0);
+    assert_eq!(frame_time(0, 1, 60, 1.0), 16);
+    assert_eq!(frame_time(0, 60, 60, 1.0), 1000);
+    assert_eq!(frame_time(500, 3, 30, 1.0), 600);
+    assert_eq!(frame_time(0, 60, 60, 1.5), 1500);
+    assert_eq!(frame_time(0, 60, 60, 0.75), 750);
+}
+
+#[test]
+fn test_temp_file_removed_on_drop() {
+    let path = std::env::temp_dir().join(format!("osr2mp4-test-{}.tmp", std::process::id()));
+    std::fs::write(&path, b"test").unwrap();
+    drop(TempFile(path.clone()));
+    assert!(!path.exists());
+    // Dropping a file that was never written is fine
+    drop(TempFile(path));
+}
@@ -0,0 +1,233 @@
+use std::str::FromStr;
+
+use glam::Vec2;
+use libosu::{prelude::Buttons, replay::ReplayAction};
+
+/// Time delta of the extra frame at the end of replays that holds the RNG seed instead of input.
+const SEED_FRAME_TIME: i64 = -12345;
+
+/// How the cursor moves between two replay frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    /// Catmull-Rom spline through the surrounding frames, smoothing out sharp corners
+    Spline,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "linear" => Ok(Interpolation::Linear),
+            "spline" => Ok(Interpolation::Spline),
+            _ => Err(format!(
+                "Unknown interpolation '{}', expected linear or spline",
+                s
+            )),
+        }
+    }
+}
+
+/// Cursor state at any point in time, between replay frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplaySample {
+    /// Cursor position in osu!pixels
+    pub position: Vec2,
+    /// Buttons held since the latest frame
+    pub buttons: Buttons,
+}
+
+/// Replay frames placed on the beatmap's timeline, sampled at arbitrary times.
+pub struct ReplaySampler {
+    frames: Vec<(i32, ReplayAction)>,
+    interpolation: Interpolation,
+}
+
+impl ReplaySampler {
+    /// Places parsed replay frames, whose times are deltas from the previous frame, on the beatmap's timeline.
+    /// Frames that step back in time can't be played back and are dropped along with the frames they overlap.
+    /// This gets rid of the setup frames every replay starts with, which sit off-screen at time 0 and -1.
+    pub fn new(actions: &[ReplayAction], interpolation: Interpolation) -> Self {
+        let mut time = 0;
+        let mut frames: Vec<(i32, ReplayAction)> = Vec::with_capacity(actions.len());
+        for action in actions {
+            if action.time == SEED_FRAME_TIME {
+                continue;
+            }
+            time += action.time as i32;
+            if action.time < 0 {
+                while frames.last().map(|(frame_ms, _)| *frame_ms >= time) == Some(true) {
+                    frames.pop();
+                }
+                continue;
+            }
+            frames.push((time, action.clone()));
+        }
+        Self {
+            frames,
+            interpolation,
+        }
+    }
+
+    /// Replay frames paired with their time in the beatmap, in time order.
+    pub fn frames(&self) -> &[(i32, ReplayAction)] {
+        &self.frames
+    }
+
+    /// Time of the last frame, in milliseconds.
+    pub fn end_ms(&self) -> i32 {
+        self.frames.last().map_or(0, |(frame_ms, _)| *frame_ms)
+    }
+
+    /// Cursor state at `time_ms`. Before the first frame and after the last one the cursor stays still.
+    pub fn sample(&self, time_ms: i32) -> ReplaySample {
+        let next = self
+            .frames
+            .partition_point(|(frame_ms, _)| *frame_ms <= time_ms);
+        let prev = match next.checked_sub(1) {
+            Some(prev) => prev,
+            None => {
+                return match self.frames.first() {
+                    Some((_, action)) => ReplaySample {
+                        position: position(action),
+                        buttons: Buttons::empty(),
+                    },
+                    None => ReplaySample {
+                        position: glam::vec2(256.0, 192.0),
+                        buttons: Buttons::empty(),
+                    },
+                }
+            }
+        };
+
+        let (prev_ms, prev_action) = &self.frames[prev];
+        let position = match self.frames.get(next) {
+            Some((next_ms, next_action)) => {
+                let t = (time_ms - prev_ms) as f32 / (next_ms - prev_ms) as f32;
+                match self.interpolation {
+                    Interpolation::Linear => position(prev_action).lerp(position(next_action), t),
+                    Interpolation::Spline => {
+                        // Ends of the replay use their own frame as the missing neighbour
+                        let before = &self.frames[prev.saturating_sub(1)].1;
+                        let after = &self.frames.get(next + 1).unwrap_or(&self.frames[next]).1;
+                        catmull_rom(
+                            position(before),
+                            position(prev_action),
+                            position(next_action),
+                            position(after),
+                            t,
+                        )
+                    }
+                }
+            }
+            None => position(prev_action),
+        };
+        ReplaySample {
+            position,
+            buttons: prev_action.buttons,
+        }
+    }
+}
+
+fn position(action: &ReplayAction) -> Vec2 {
+    glam::vec2(action.x, action.y)
+}
+
+/// Point `t` of the way from `p1` to `p2` on a Catmull-Rom spline.
+fn catmull_rom(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+#[cfg(test)]
+fn action(time: i64, x: f32, y: f32, buttons: Buttons) -> ReplayAction {
+    ReplayAction {
+        time,
+        x,
+        y,
+        buttons,
+    }
+}
+
+/// A replay frame already placed on the beatmap's timeline at `time`, for tests of anything reading replay frames.
+#[cfg(test)]
+pub fn frame(time: i32, x: f32, y: f32, buttons: Buttons) -> (i32, ReplayAction) {
+    (time, action(0, x, y, buttons))
+}
+
+#[test]
+fn test_sampler_frames() {
+    let actions = [
+        action(0, 256.0, -500.0, Buttons::empty()),
+        action(-1, 256.0, -500.0, Buttons::empty()),
+        action(1001, 100.0, 100.0, Buttons::empty()),
+        action(20, 200.0, 100.0, Buttons::K1 | Buttons::M1),
+        action(SEED_FRAME_TIME, 0.0, 0.0, Buttons::empty()),
+    ];
+    let sampler = ReplaySampler::new(&actions, Interpolation::Linear);
+    let times: Vec<_> = sampler.frames().iter().map(|(time, _)| *time).collect();
+    assert_eq!(times, [1000, 1020]);
+    assert_eq!(sampler.end_ms(), 1020);
+}
+
+#[test]
+fn test_sample_linear() {
+    let actions = [
+        action(0, 100.0, 100.0, Buttons::empty()),
+        action(20, 200.0, 100.0, Buttons::K1 | Buttons::M1),
+        action(20, 200.0, 300.0, Buttons::empty()),
+    ];
+    let sampler = ReplaySampler::new(&actions, Interpolation::Linear);
+    assert_eq!(
+        sampler.sample(-10),
+        ReplaySample {
+            position: glam::vec2(100.0, 100.0),
+            buttons: Buttons::empty(),
+        }
+    );
+    assert_eq!(sampler.sample(5).position, glam::vec2(125.0, 100.0));
+    assert_eq!(
+        sampler.sample(30),
+        ReplaySample {
+            position: glam::vec2(200.0, 200.0),
+            buttons: Buttons::K1 | Buttons::M1,
+        }
+    );
+    assert_eq!(sampler.sample(100).position, glam::vec2(200.0, 300.0));
+    assert_eq!(
+        ReplaySampler::new(&[], Interpolation::Linear)
+            .sample(0)
+            .position,
+        glam::vec2(256.0, 192.0)
+    );
+}
+
+#[test]
+fn test_sample_spline() {
+    let actions = [
+        action(0, 0.0, 0.0, Buttons::empty()),
+        action(10, 10.0, 0.0, Buttons::empty()),
+        action(10, 20.0, 0.0, Buttons::empty()),
+        action(10, 20.0, 10.0, Buttons::empty()),
+    ];
+    let sampler = ReplaySampler::new(&actions, Interpolation::Spline);
+    // Passes through every frame
+    assert_eq!(sampler.sample(10).position, glam::vec2(10.0, 0.0));
+    assert_eq!(sampler.sample(20).position, glam::vec2(20.0, 0.0));
+    // Overshoots slightly before turning the corner instead of following straight lines
+    let position = sampler.sample(15).position;
+    assert!(position.x > 15.0 && position.y < 0.0);
+}
+
+#[test]
+fn test_parse_interpolation() {
+    assert_eq!("linear".parse(), Ok(Interpolation::Linear));
+    assert_eq!("Spline".parse(), Ok(Interpolation::Spline));
+    assert!("cubic".parse::<Interpolation>().is_err());
+}
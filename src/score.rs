@@ -0,0 +1,435 @@
+use libosu::prelude::*;
+
+use crate::{
+    judgement::{HitResult, ObjectJudgement, SliderEvent, SliderEventKind},
+    BeatmapData,
+};
+
+/// Running totals of a play at some point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScoreState {
+    pub score: u32,
+    pub combo: u32,
+    pub max_combo: u32,
+    pub count_300: u32,
+    pub count_100: u32,
+    pub count_50: u32,
+    pub count_miss: u32,
+}
+
+impl ScoreState {
+    /// Accuracy from 0.0 to 1.0, 1.0 before anything has been judged.
+    pub fn accuracy(&self) -> f64 {
+        let judged = self.count_300 + self.count_100 + self.count_50 + self.count_miss;
+        if judged == 0 {
+            return 1.0;
+        }
+        (self.count_300 * 300 + self.count_100 * 100 + self.count_50 * 50) as f64
+            / (judged * 300) as f64
+    }
+
+    fn add_combo(&mut self) {
+        self.combo += 1;
+        self.max_combo = self.max_combo.max(self.combo);
+    }
+}
+
+/// Score multiplier of the mods that affect ScoreV1.
+pub fn score_multiplier(mods: Mods) -> f64 {
+    mod_multiplier(
+        mods,
+        &[
+            (Mods::Easy, 0.5),
+            (Mods::NoFail, 0.5),
+            (Mods::HalfTime, 0.3),
+            (Mods::HardRock, 1.06),
+            (Mods::Hidden, 1.06),
+            (Mods::DoubleTime | Mods::Nightcore, 1.12),
+            (Mods::Flashlight, 1.12),
+            (Mods::SpunOut, 0.9),
+        ],
+    )
+}
+
+/// Score multiplier of the mods that affect ScoreV2, which rewards the harder mods more.
+pub fn score_v2_multiplier(mods: Mods) -> f64 {
+    mod_multiplier(
+        mods,
+        &[
+            (Mods::Easy, 0.5),
+            (Mods::NoFail, 0.5),
+            (Mods::HalfTime, 0.3),
+            (Mods::HardRock, 1.10),
+            (Mods::Hidden, 1.06),
+            (Mods::DoubleTime | Mods::Nightcore, 1.20),
+            (Mods::Flashlight, 1.12),
+            (Mods::SpunOut, 0.9),
+        ],
+    )
+}
+
+/// Product of the multipliers in `table` whose mods are on. Nightcore replays also have
+/// DoubleTime set, so entries count once however many of their mods are on.
+fn mod_multiplier(mods: Mods, table: &[(Mods, f64)]) -> f64 {
+    table
+        .iter()
+        .filter(|(m, _)| mods.intersects(*m))
+        .map(|(_, multiplier)| multiplier)
+        .product()
+}
+
+/// ScoreV1 difficulty multiplier, from the beatmap's settings and how dense it is.
+/// Density only counts drain time, so breaks don't make a beatmap look sparser.
+pub fn difficulty_multiplier(beatmap: &Beatmap) -> f64 {
+    let first = beatmap.hit_objects.first().map(|o| o.start_time.0);
+    let last = beatmap.hit_objects.last().map(|o| match &o.kind {
+        HitObjectKind::Spinner(SpinnerInfo { end_time }) => end_time.0,
+        _ => o.start_time.0,
+    });
+    let break_ms: i32 = beatmap
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Break(b) => Some((b.end_time.0 - b.start_time.0).max(0)),
+            _ => None,
+        })
+        .sum();
+    let drain_seconds = match (first, last) {
+        (Some(first), Some(last)) => ((last - first - break_ms) as f64 / 1000.0).max(1.0),
+        _ => 1.0,
+    };
+    let density = (beatmap.hit_objects.len() as f64 / drain_seconds * 8.0).clamp(0.0, 16.0);
+    let difficulty = &beatmap.difficulty;
+    (((difficulty.hp_drain_rate + difficulty.circle_size + difficulty.overall_difficulty) as f64
+        + density)
+        / 38.0
+        * 5.0)
+        .round()
+}
+
+fn hit_value(result: HitResult) -> u32 {
+    match result {
+        HitResult::Hit300 => 300,
+        HitResult::Hit100 => 100,
+        HitResult::Hit50 => 50,
+        HitResult::Miss => 0,
+    }
+}
+
+/// Something that changes the score at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScoreEvent {
+    SliderEvent(SliderEventKind, bool),
+    /// A full spin of a spinner, and whether it was past the spins needed to clear it
+    SpinnerSpin(bool),
+    /// Circles and spinners, which only affect combo through their result
+    Circle(HitResult),
+    Slider(HitResult),
+}
+
+fn score_events(judgements: &[ObjectJudgement]) -> Vec<(i32, ScoreEvent)> {
+    let mut events = Vec::new();
+    for judgement in judgements {
+        events.extend(
+            judgement
+                .spinner_spins()
+                .iter()
+                .map(|spin| (spin.time_ms, ScoreEvent::SpinnerSpin(spin.bonus))),
+        );
+        if judgement.slider_events.is_empty() {
+            events.push((judgement.time_ms, ScoreEvent::Circle(judgement.result)));
+        } else {
+            events.extend(judgement.slider_events.iter().map(|event| {
+                (
+                    event.time_ms,
+                    ScoreEvent::SliderEvent(event.kind, event.hit),
+                )
+            }));
+            events.push((judgement.time_ms, ScoreEvent::Slider(judgement.result)));
+        }
+    }
+    // Stable sort keeps a slider's own result after its tail, and a spinner's after its last spin
+    events.sort_by_key(|(time, _)| *time);
+    events
+}
+
+/// Score, combo and accuracy over the course of the replay.
+pub struct ScoreTimeline {
+    states: Vec<(i32, ScoreState)>,
+}
+
+impl ScoreTimeline {
+    /// Uses ScoreV2 when the replay was played with it, ScoreV1 otherwise.
+    pub fn new(map_data: &BeatmapData, judgements: &[ObjectJudgement]) -> Self {
+        let mods = map_data.mods;
+        let events = score_events(judgements);
+        let states = if mods.contains(Mods::ScoreV2) {
+            // ScoreV2 scales the combo portion by what a full combo of the same objects would give
+            let perfect: Vec<_> = judgements
+                .iter()
+                .map(|judgement| ObjectJudgement {
+                    result: HitResult::Hit300,
+                    slider_events: judgement
+                        .slider_events
+                        .iter()
+                        .map(|&event| SliderEvent { hit: true, ..event })
+                        .collect(),
+                    ..judgement.clone()
+                })
+                .collect();
+            let max_combo_score = simulate_v2(&score_events(&perfect), 1.0, 1.0).1;
+            simulate_v2(&events, max_combo_score, score_v2_multiplier(mods)).0
+        } else {
+            simulate_v1(
+                &events,
+                difficulty_multiplier(&map_data.beatmap) * score_multiplier(mods),
+            )
+        };
+        Self { states }
+    }
+
+    /// Totals after everything judged at or before `time_ms`.
+    pub fn at(&self, time_ms: i32) -> ScoreState {
+        let i = self.states.partition_point(|(time, _)| *time <= time_ms);
+        i.checked_sub(1)
+            .map(|i| self.states[i].1)
+            .unwrap_or_default()
+    }
+
+    /// Totals at the end of the replay.
+    pub fn last(&self) -> ScoreState {
+        self.states
+            .last()
+            .map(|(_, state)| *state)
+            .unwrap_or_default()
+    }
+}
+
+/// Applies an event's effect on combo and hit counts, returning the hit value it awards
+/// and the combo before it.
+fn apply_event(state: &mut ScoreState, event: ScoreEvent) -> (u32, u32) {
+    let combo_before = state.combo;
+    let value = match event {
+        ScoreEvent::SliderEvent(kind, true) => {
+            state.add_combo();
+            match kind {
+                SliderEventKind::Tick => 10,
+                _ => 30,
+            }
+        }
+        // Dropping the tail doesn't break combo, it just doesn't give any
+        ScoreEvent::SliderEvent(SliderEventKind::Tail, false) => 0,
+        ScoreEvent::SliderEvent(_, false) => {
+            state.combo = 0;
+            0
+        }
+        // Spins give score on their own, without any combo
+        ScoreEvent::SpinnerSpin(false) => 100,
+        ScoreEvent::SpinnerSpin(true) => 1000,
+        ScoreEvent::Circle(result) | ScoreEvent::Slider(result) => {
+            match result {
+                HitResult::Hit300 => state.count_300 += 1,
+                HitResult::Hit100 => state.count_100 += 1,
+                HitResult::Hit50 => state.count_50 += 1,
+                HitResult::Miss => state.count_miss += 1,
+            }
+            if let ScoreEvent::Circle(result) = event {
+                if result == HitResult::Miss {
+                    state.combo = 0;
+                } else {
+                    state.add_combo();
+                }
+            }
+            hit_value(result)
+        }
+    };
+    (value, combo_before)
+}
+
+fn simulate_v1(events: &[(i32, ScoreEvent)], multiplier: f64) -> Vec<(i32, ScoreState)> {
+    let mut state = ScoreState::default();
+    events
+        .iter()
+        .map(|&(time, event)| {
+            let (value, combo_before) = apply_event(&mut state, event);
+            let bonus = match event {
+                ScoreEvent::Circle(_) | ScoreEvent::Slider(_) => {
+                    value as f64 * combo_before.saturating_sub(1) as f64 * multiplier / 25.0
+                }
+                ScoreEvent::SliderEvent(..) | ScoreEvent::SpinnerSpin(_) => 0.0,
+            };
+            state.score += value + bonus as u32;
+            (time, state)
+        })
+        .collect()
+}
+
+/// Returns the states and the final combo portion before scaling.
+fn simulate_v2(
+    events: &[(i32, ScoreEvent)],
+    max_combo_score: f64,
+    multiplier: f64,
+) -> (Vec<(i32, ScoreState)>, f64) {
+    let mut state = ScoreState::default();
+    let mut combo_score = 0.0;
+    let mut bonus_score = 0;
+    let mut judged = 0;
+    let total = events
+        .iter()
+        .filter(|(_, event)| matches!(event, ScoreEvent::Circle(_) | ScoreEvent::Slider(_)))
+        .count()
+        .max(1);
+    let states = events
+        .iter()
+        .map(|&(time, event)| {
+            let (value, _) = apply_event(&mut state, event);
+            match event {
+                ScoreEvent::Circle(_) | ScoreEvent::Slider(_) => {
+                    combo_score += value as f64 * (1.0 + state.combo as f64 / 10.0);
+                    judged += 1;
+                }
+                // Spinner bonus goes on top of the scaled portions
+                ScoreEvent::SpinnerSpin(_) => bonus_score += value,
+                ScoreEvent::SliderEvent(..) => {}
+            }
+            let combo_portion = 700_000.0 * combo_score / max_combo_score.max(1.0);
+            let accuracy_portion =
+                300_000.0 * state.accuracy().powi(10) * judged as f64 / total as f64;
+            state.score = ((combo_portion + accuracy_portion) * multiplier) as u32 + bonus_score;
+            (time, state)
+        })
+        .collect();
+    (states, combo_score)
+}
+
+/// Differences between the simulated totals and the ones stored in the replay.
+pub fn verify(state: &ScoreState, replay: &Replay) -> Vec<String> {
+    [
+        ("300s", state.count_300, replay.count_300 as u32),
+        ("100s", state.count_100, replay.count_100 as u32),
+        ("50s", state.count_50, replay.count_50 as u32),
+        ("misses", state.count_miss, replay.count_miss as u32),
+        ("max combo", state.max_combo, replay.max_combo as u32),
+        ("score", state.score, replay.score),
+    ]
+    .iter()
+    .filter(|(_, simulated, expected)| simulated != expected)
+    .map(|(name, simulated, expected)| {
+        format!("{}: simulated {}, replay has {}", name, simulated, expected)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+fn circle(time_ms: i32, result: HitResult) -> ObjectJudgement {
+    ObjectJudgement {
+        result,
+        time_ms,
+        hit_offset: None,
+        slider_events: Vec::new(),
+        spinner: None,
+    }
+}
+
+#[test]
+fn test_accuracy() {
+    let state = ScoreState {
+        count_300: 2,
+        count_100: 1,
+        count_50: 1,
+        count_miss: 1,
+        ..ScoreState::default()
+    };
+    assert!((state.accuracy() - 750.0 / 1500.0).abs() < 1e-9);
+    assert_eq!(ScoreState::default().accuracy(), 1.0);
+}
+
+#[test]
+fn test_score_multiplier() {
+    assert_eq!(score_multiplier(Mods::empty()), 1.0);
+    assert!((score_multiplier(Mods::Hidden | Mods::HardRock) - 1.06 * 1.06).abs() < 1e-9);
+    assert!((score_multiplier(Mods::HalfTime | Mods::Easy) - 0.15).abs() < 1e-9);
+    assert!((score_multiplier(Mods::DoubleTime | Mods::Nightcore) - 1.12).abs() < 1e-9);
+}
+
+#[test]
+fn test_score_v2_multiplier() {
+    assert_eq!(score_v2_multiplier(Mods::ScoreV2), 1.0);
+    assert!((score_v2_multiplier(Mods::Hidden | Mods::HardRock) - 1.06 * 1.10).abs() < 1e-9);
+    assert!((score_v2_multiplier(Mods::DoubleTime | Mods::Nightcore) - 1.20).abs() < 1e-9);
+    assert!((score_v2_multiplier(Mods::Flashlight | Mods::SpunOut) - 1.12 * 0.9).abs() < 1e-9);
+}
+
+#[test]
+fn test_simulate_v1_combo() {
+    let judgements = vec![
+        circle(100, HitResult::Hit300),
+        circle(200, HitResult::Hit300),
+        circle(300, HitResult::Hit100),
+        circle(400, HitResult::Miss),
+        circle(500, HitResult::Hit300),
+    ];
+    let states = simulate_v1(&score_events(&judgements), 2.0);
+    let last = states.last().unwrap().1;
+    assert_eq!(last.max_combo, 3);
+    assert_eq!(last.combo, 1);
+    assert_eq!((last.count_300, last.count_100, last.count_miss), (3, 1, 1));
+    // 300 + (300 + 300 * 0) + (100 + 100 * 1 * 2 / 25) + 0 + 300
+    assert_eq!(last.score, 300 + 300 + 108 + 300);
+}
+
+#[test]
+fn test_slider_events_combo() {
+    let event = |kind, time_ms, hit| SliderEvent { kind, time_ms, hit };
+    let judgements = vec![ObjectJudgement {
+        result: HitResult::Hit100,
+        time_ms: 400,
+        hit_offset: Some(0),
+        slider_events: vec![
+            event(SliderEventKind::Head, 0, true),
+            event(SliderEventKind::Tick, 100, false),
+            event(SliderEventKind::Repeat, 200, true),
+            event(SliderEventKind::Tail, 364, false),
+        ],
+        spinner: None,
+    }];
+    let timeline = simulate_v1(&score_events(&judgements), 1.0);
+    let last = timeline.last().unwrap().1;
+    assert_eq!(last.combo, 1);
+    assert_eq!(last.max_combo, 1);
+    assert_eq!(last.count_100, 1);
+    assert_eq!(last.score, 30 + 30 + 100);
+}
+
+#[test]
+fn test_spinner_spins_score() {
+    // A quarter turn every 50ms from 300ms, for full spins at 500, 700 and 900ms
+    let frames: Vec<_> = (0..=12)
+        .map(|i| {
+            crate::spinner::spin_frame(
+                300 + i * 50,
+                i as f32 * std::f32::consts::FRAC_PI_2,
+                Buttons::K1 | Buttons::M1,
+            )
+        })
+        .collect();
+    let judgements = vec![
+        circle(100, HitResult::Hit300),
+        ObjectJudgement {
+            result: HitResult::Hit300,
+            time_ms: 1000,
+            hit_offset: None,
+            slider_events: Vec::new(),
+            spinner: Some(crate::spinner::SpinnerSpins::new(
+                &frames, 300, 1000, 1.0, 2,
+            )),
+        },
+    ];
+    let states = simulate_v1(&score_events(&judgements), 1.0);
+    // Spins don't add to the combo
+    assert_eq!(states[3].1.combo, 1);
+    let last = states.last().unwrap().1;
+    assert_eq!(last.combo, 2);
+    assert_eq!(last.count_300, 2);
+    assert_eq!(last.score, 300 + 100 + 100 + 1000 + 300);
+}
@@ -0,0 +1,129 @@
+//! The skin used for anything a skin folder doesn't provide, drawn with the software backend
+//! so no image files have to be shipped.
+
+use ggez::{graphics::Color, GameResult};
+use image::RgbaImage;
+
+use crate::graphics::{
+    backend::{Backend, DrawMode},
+    cpu::CpuBackend,
+};
+
+/// Default skin images are drawn at @2x resolution.
+pub const DPI_SCALE: f32 = 2.0;
+
+const WHITE: Color = Color {
+    r: 1.0,
+    g: 1.0,
+    b: 1.0,
+    a: 1.0,
+};
+
+/// Draws a `size`x`size` image on a transparent background.
+fn draw(size: u16, f: impl FnOnce(&mut CpuBackend, glam::Vec2) -> GameResult) -> RgbaImage {
+    let mut backend = CpuBackend::new(size, size);
+    let center = glam::vec2(size as f32 / 2.0, size as f32 / 2.0);
+    f(&mut backend, center).expect("Software rendering doesn't fail");
+    backend.into_image()
+}
+
+fn filled_circle(size: u16, radius: f32, color: Color) -> RgbaImage {
+    draw(size, |backend, center| {
+        backend.draw_circle(DrawMode::Fill, center, radius, color)
+    })
+}
+
+fn ring(size: u16, radius: f32, width: f32, color: Color) -> RgbaImage {
+    draw(size, |backend, center| {
+        backend.draw_circle(DrawMode::Stroke(width), center, radius, color)
+    })
+}
+
+fn text(text: &str, color: Color) -> RgbaImage {
+    let glyph_size = 64.0;
+    let mut backend = CpuBackend::new((glyph_size as usize * text.chars().count()) as u16, 64);
+    backend
+        .draw_text(text, glam::vec2(0.0, 0.0), glyph_size, color)
+        .expect("Software rendering doesn't fail");
+    backend.into_image()
+}
+
+/// Generates the default skin's version of the element called `name`.
+/// Elements the default skin doesn't draw are blank.
+pub fn element(name: &str) -> RgbaImage {
+    match name {
+        "hitcircle" | "sliderstartcircle" | "sliderendcircle" => {
+            filled_circle(256, 118.0, Color { a: 0.8, ..WHITE })
+        }
+        "hitcircleoverlay" | "sliderstartcircleoverlay" | "sliderendcircleoverlay" => {
+            ring(256, 112.0, 16.0, WHITE)
+        }
+        "approachcircle" => ring(256, 120.0, 8.0, WHITE),
+        "sliderb" => filled_circle(256, 112.0, WHITE),
+        "sliderfollowcircle" => ring(512, 248.0, 8.0, WHITE),
+        "sliderscorepoint" => filled_circle(32, 10.0, WHITE),
+        "reversearrow" => draw(128, |backend, center| {
+            backend.draw_polyline(
+                DrawMode::Fill,
+                &[
+                    center + glam::vec2(40.0, 0.0),
+                    center + glam::vec2(-24.0, -40.0),
+                    center + glam::vec2(-24.0, 40.0),
+                ],
+                WHITE,
+            )
+        }),
+        "cursor" => draw(96, |backend, center| {
+            backend.draw_circle(DrawMode::Fill, center, 40.0, Color::new(1.0, 0.8, 0.2, 1.0))?;
+            backend.draw_circle(DrawMode::Stroke(6.0), center, 40.0, WHITE)
+        }),
+        "inputoverlay-key" => draw(92, |backend, _| {
+            backend.draw_rect(
+                DrawMode::Fill,
+                glam::vec2(6.0, 6.0),
+                glam::vec2(80.0, 80.0),
+                WHITE,
+            )
+        }),
+        "cursortrail" => filled_circle(64, 24.0, Color::new(1.0, 0.8, 0.2, 0.5)),
+        "cursormiddle" => filled_circle(32, 8.0, WHITE),
+        "spinner-circle" => ring(768, 360.0, 24.0, WHITE),
+        "spinner-clear" => text("CLEAR", WHITE),
+        "spinner-approachcircle" => ring(768, 376.0, 12.0, WHITE),
+        "followpoint" => draw(64, |backend, center| {
+            backend.draw_rect(
+                DrawMode::Fill,
+                center - glam::vec2(16.0, 3.0),
+                glam::vec2(32.0, 6.0),
+                WHITE,
+            )
+        }),
+        "hit0" => text("X", Color::new(1.0, 0.0, 0.0, 1.0)),
+        "hit50" => text("50", Color::new(1.0, 0.8, 0.2, 1.0)),
+        "hit100" => text("100", Color::new(0.4, 1.0, 0.4, 1.0)),
+        "hit300" => text("300", Color::new(0.4, 0.8, 1.0, 1.0)),
+        // Digits and symbols of any font prefix, like `default-1` or `score-x`
+        _ => match name.rsplit('-').next() {
+            Some(digit) if digit.len() == 1 && digit.chars().all(|c| c.is_ascii_digit()) => {
+                text(digit, WHITE)
+            }
+            Some("comma") => text(",", WHITE),
+            Some("dot") => text(".", WHITE),
+            Some("percent") => text("%", WHITE),
+            Some("x") => text("x", WHITE),
+            _ => RgbaImage::new(1, 1),
+        },
+    }
+}
+
+#[test]
+fn test_default_elements() {
+    let hitcircle = element("hitcircle");
+    assert_eq!(hitcircle.dimensions(), (256, 256));
+    assert_eq!(hitcircle.get_pixel(128, 128).0, [255, 255, 255, 204]);
+    assert_eq!(hitcircle.get_pixel(0, 0).0[3], 0);
+
+    assert_eq!(element("default-7").dimensions(), (64, 64));
+    assert_eq!(element("score-percent").dimensions(), (64, 64));
+    assert_eq!(element("no-such-element").dimensions(), (1, 1));
+}
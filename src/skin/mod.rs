@@ -0,0 +1,383 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use ggez::graphics::Color;
+use glam::Vec2;
+
+use crate::graphics::backend::Texture;
+
+mod default;
+
+/// Version osu! assumes for skins without a skin.ini.
+pub const LATEST_VERSION: f32 = 2.7;
+
+/// Settings read from a skin's skin.ini.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkinIni {
+    pub name: String,
+    pub author: String,
+    pub version: f32,
+    pub cursor_expand: bool,
+    pub cursor_centre: bool,
+    pub cursor_rotate: bool,
+    pub cursor_trail_rotate: bool,
+    /// Frames per second of animated elements, `None` to play each animation over a second
+    pub animation_framerate: Option<f32>,
+    pub combo_colors: Vec<Color>,
+    pub slider_border: Color,
+    pub slider_track_override: Option<Color>,
+    pub hit_circle_prefix: String,
+    pub hit_circle_overlap: i32,
+}
+
+impl Default for SkinIni {
+    fn default() -> Self {
+        Self {
+            name: "osr2mp4-rs default".to_owned(),
+            author: String::new(),
+            version: LATEST_VERSION,
+            cursor_expand: true,
+            cursor_centre: true,
+            cursor_rotate: true,
+            cursor_trail_rotate: true,
+            animation_framerate: None,
+            combo_colors: vec![
+                Color::from_rgb(255, 192, 0),
+                Color::from_rgb(0, 202, 0),
+                Color::from_rgb(18, 124, 255),
+                Color::from_rgb(242, 24, 57),
+            ],
+            slider_border: Color::from_rgb(255, 255, 255),
+            slider_track_override: None,
+            hit_circle_prefix: "default".to_owned(),
+            hit_circle_overlap: -2,
+        }
+    }
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    let components = value
+        .split(',')
+        .map(|c| c.trim().parse::<u8>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    match components[..] {
+        [r, g, b] => Some(Color::from_rgb(r, g, b)),
+        [r, g, b, a] => Some(Color::from_rgba(r, g, b, a)),
+        _ => None,
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "1" => Some(true),
+        "0" => Some(false),
+        _ => value.parse().ok(),
+    }
+}
+
+impl SkinIni {
+    /// Parses the contents of a skin.ini, keeping the defaults for anything missing or malformed.
+    pub fn parse(text: &str) -> Self {
+        let mut ini = Self {
+            // Skins that don't say which version they target are treated as the oldest one
+            version: 1.0,
+            ..Self::default()
+        };
+        let mut combo_colors = Vec::new();
+        let mut section = String::new();
+        for line in text.trim_start_matches('\u{feff}').lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].to_owned();
+                continue;
+            }
+            let (key, value) = match line.find(':') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => continue,
+            };
+            // Trailing comments are common in hand-edited skins
+            let value = value.split("//").next().unwrap_or("").trim();
+
+            match (section.as_str(), key) {
+                ("General", "Name") => ini.name = value.to_owned(),
+                ("General", "Author") => ini.author = value.to_owned(),
+                ("General", "Version") => {
+                    if value == "latest" {
+                        ini.version = LATEST_VERSION;
+                    } else if let Ok(version) = value.parse() {
+                        ini.version = version;
+                    }
+                }
+                ("General", "CursorExpand") => {
+                    ini.cursor_expand = parse_bool(value).unwrap_or(ini.cursor_expand)
+                }
+                ("General", "CursorCentre") => {
+                    ini.cursor_centre = parse_bool(value).unwrap_or(ini.cursor_centre)
+                }
+                ("General", "CursorRotate") => {
+                    ini.cursor_rotate = parse_bool(value).unwrap_or(ini.cursor_rotate)
+                }
+                ("General", "CursorTrailRotate") => {
+                    ini.cursor_trail_rotate = parse_bool(value).unwrap_or(ini.cursor_trail_rotate)
+                }
+                ("General", "AnimationFramerate") => {
+                    // Negative means the default, like leaving it out
+                    ini.animation_framerate = value.parse().ok().filter(|&fps: &f32| fps > 0.0)
+                }
+                ("Colours", "SliderBorder") => {
+                    ini.slider_border = parse_color(value).unwrap_or(ini.slider_border)
+                }
+                ("Colours", "SliderTrackOverride") => {
+                    ini.slider_track_override = parse_color(value)
+                }
+                ("Colours", key) if key.starts_with("Combo") => {
+                    if let (Ok(index), Some(color)) =
+                        (key[5..].parse::<usize>(), parse_color(value))
+                    {
+                        combo_colors.push((index, color));
+                    }
+                }
+                ("Fonts", "HitCirclePrefix") => {
+                    ini.hit_circle_prefix = value.replace('\\', "/");
+                }
+                ("Fonts", "HitCircleOverlap") => {
+                    ini.hit_circle_overlap = value.parse().unwrap_or(ini.hit_circle_overlap)
+                }
+                _ => {}
+            }
+        }
+        if !combo_colors.is_empty() {
+            combo_colors.sort_by_key(|(index, _)| *index);
+            ini.combo_colors = combo_colors.into_iter().map(|(_, color)| color).collect();
+        }
+        ini
+    }
+}
+
+/// A skin image, along with the resolution it was made for.
+pub struct SkinElement {
+    pub texture: Rc<Texture>,
+    /// 2 for @2x (high definition) images, 1 otherwise
+    pub dpi_scale: f32,
+}
+
+impl SkinElement {
+    /// Size of the element in osu!pixels, as the game would draw it unscaled.
+    pub fn size(&self) -> Vec2 {
+        self.texture.size() / self.dpi_scale
+    }
+}
+
+/// Element name, and the number of numbered elements like `default-1` or `hit300-0`.
+type ElementKey = (String, Option<u32>);
+
+/// A skin folder, with every missing image filled in by the built-in default skin.
+/// Images are loaded the first time they're asked for and shared afterwards.
+pub struct Skin {
+    dir: Option<PathBuf>,
+    pub ini: SkinIni,
+    /// Whether spinners are drawn with the newer bottom and top images instead of a background and circle
+    pub new_style_spinner: bool,
+    cache: RefCell<HashMap<ElementKey, Rc<SkinElement>>>,
+    animations: RefCell<HashMap<String, Rc<[Rc<SkinElement>]>>>,
+}
+
+impl Skin {
+    /// Loads the skin in `dir`, or only the default skin without one.
+    pub fn load(dir: Option<&Path>) -> Self {
+        let ini = match dir.map(|dir| dir.join("skin.ini")) {
+            Some(path) if path.is_file() => match std::fs::read(&path) {
+                Ok(bytes) => SkinIni::parse(&String::from_utf8_lossy(&bytes)),
+                Err(e) => {
+                    eprintln!("Couldn't read {}: {}", path.display(), e);
+                    SkinIni::default()
+                }
+            },
+            _ => SkinIni::default(),
+        };
+        let mut skin = Self {
+            dir: dir.map(Path::to_path_buf),
+            ini,
+            new_style_spinner: false,
+            cache: RefCell::new(HashMap::new()),
+            animations: RefCell::new(HashMap::new()),
+        };
+        skin.new_style_spinner = skin.element_path("spinner-bottom").is_some();
+        skin
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Image file of `name` in the skin folder, preferring the @2x version.
+    pub fn element_path(&self, name: &str) -> Option<(PathBuf, f32)> {
+        let dir = self.dir.as_ref()?;
+        [
+            (format!("{}@2x.png", name), 2.0),
+            (format!("{}.png", name), 1.0),
+        ]
+        .iter()
+        .map(|(file, scale)| (dir.join(file), *scale))
+        .find(|(path, _)| path.is_file())
+    }
+
+    /// The skin's `name` image, e.g. `hitcircle`.
+    /// Falls back to the default skin if the skin doesn't have it or it can't be loaded.
+    pub fn element(&self, name: &str) -> Rc<SkinElement> {
+        self.cached((name.to_owned(), None))
+    }
+
+    /// The skin's `{name}-{number}` image, e.g. `default-1` for digits.
+    pub fn numbered_element(&self, name: &str, number: u32) -> Rc<SkinElement> {
+        self.cached((name.to_owned(), Some(number)))
+    }
+
+    /// Frames of the skin's `name` animation, like `hit300-0`, `hit300-1` and so on,
+    /// except for the slider ball whose frames are `sliderb0`, `sliderb1` without the dash.
+    /// Skins without the animation get the single `name` image as the only frame.
+    /// The skin folder is only searched for frames the first time an animation is asked for.
+    pub fn animation(&self, name: &str) -> Rc<[Rc<SkinElement>]> {
+        if let Some(frames) = self.animations.borrow().get(name) {
+            return frames.clone();
+        }
+        let frame_name = |frame: u32| match name {
+            "sliderb" => format!("{}{}", name, frame),
+            _ => format!("{}-{}", name, frame),
+        };
+        let frame_count = (0..)
+            .take_while(|&frame| self.element_path(&frame_name(frame)).is_some())
+            .count() as u32;
+        let frames: Rc<[Rc<SkinElement>]> = if frame_count == 0 {
+            Rc::new([self.element(name)])
+        } else {
+            (0..frame_count)
+                .map(|frame| self.element(&frame_name(frame)))
+                .collect()
+        };
+        self.animations
+            .borrow_mut()
+            .insert(name.to_owned(), frames.clone());
+        frames
+    }
+
+    fn cached(&self, key: ElementKey) -> Rc<SkinElement> {
+        if let Some(element) = self.cache.borrow().get(&key) {
+            return element.clone();
+        }
+        let name = match key.1 {
+            Some(number) => format!("{}-{}", key.0, number),
+            None => key.0.clone(),
+        };
+        let element = Rc::new(self.load_element(&name));
+        self.cache.borrow_mut().insert(key, element.clone());
+        element
+    }
+
+    fn load_element(&self, name: &str) -> SkinElement {
+        if let Some((path, dpi_scale)) = self.element_path(name) {
+            match Texture::from_path(&path) {
+                Ok(texture) => return SkinElement { texture, dpi_scale },
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        SkinElement {
+            texture: Texture::new(default::element(name)),
+            dpi_scale: default::DPI_SCALE,
+        }
+    }
+
+    /// Combo colours to use for the beatmap, which override the skin's when it has its own.
+    pub fn combo_colors(&self, beatmap_colors: &[Color]) -> Vec<Color> {
+        if beatmap_colors.is_empty() {
+            self.ini.combo_colors.clone()
+        } else {
+            beatmap_colors.to_vec()
+        }
+    }
+}
+
+#[test]
+fn test_parse_skin_ini() {
+    let ini = SkinIni::parse(
+        "\u{feff}[General]\n\
+         Name: Test Skin\n\
+         Version: 2.5\n\
+         CursorExpand: 0\n\
+         AnimationFramerate: 30\n\
+         \n\
+         [Colours]\n\
+         Combo2: 0, 0, 255\n\
+         Combo1 : 255,0,0 // red\n\
+         SliderBorder: 10,20,30\n\
+         SliderTrackOverride: 1,2,3\n\
+         \n\
+         [Fonts]\n\
+         HitCirclePrefix: numbers\\default\n\
+         HitCircleOverlap: 5\n",
+    );
+    assert_eq!(ini.name, "Test Skin");
+    assert_eq!(ini.version, 2.5);
+    assert!(!ini.cursor_expand);
+    assert!(ini.cursor_rotate);
+    assert_eq!(ini.animation_framerate, Some(30.0));
+    assert_eq!(
+        ini.combo_colors,
+        vec![Color::from_rgb(255, 0, 0), Color::from_rgb(0, 0, 255)]
+    );
+    assert_eq!(ini.slider_border, Color::from_rgb(10, 20, 30));
+    assert_eq!(ini.slider_track_override, Some(Color::from_rgb(1, 2, 3)));
+    assert_eq!(ini.hit_circle_prefix, "numbers/default");
+    assert_eq!(ini.hit_circle_overlap, 5);
+}
+
+#[test]
+fn test_parse_skin_ini_defaults() {
+    let ini = SkinIni::parse(
+        "[General]\nVersion: latest\nAnimationFramerate: -1\n[Colours]\nSliderBorder: nope\n",
+    );
+    assert_eq!(ini.version, LATEST_VERSION);
+    assert_eq!(ini.animation_framerate, None);
+    assert_eq!(ini.slider_border, SkinIni::default().slider_border);
+    assert_eq!(ini.combo_colors, SkinIni::default().combo_colors);
+    assert_eq!(SkinIni::parse("").version, 1.0);
+}
+
+#[test]
+fn test_element_cache() {
+    let skin = Skin::load(None);
+    let first = skin.numbered_element("default", 1);
+    assert!(Rc::ptr_eq(&first, &skin.numbered_element("default", 1)));
+    assert!(!Rc::ptr_eq(&first, &skin.numbered_element("default", 2)));
+    assert!(!Rc::ptr_eq(&first, &skin.element("default")));
+    assert_eq!(first.dpi_scale, default::DPI_SCALE);
+    let animation = skin.animation("hit300");
+    assert_eq!(animation.len(), 1);
+    assert!(Rc::ptr_eq(&animation, &skin.animation("hit300")));
+}
+
+#[test]
+fn test_animation_frames() {
+    let dir = std::env::temp_dir().join(format!("osr2mp4-skin-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let image = image::RgbaImage::new(1, 1);
+    for name in &["sliderb0", "sliderb1", "hit100-0", "hit100-1", "hit100-2"] {
+        image.save(dir.join(format!("{}.png", name))).unwrap();
+    }
+    let skin = Skin::load(Some(&dir));
+    let sliderb = skin.animation("sliderb").len();
+    let hit100 = skin.animation("hit100").len();
+    let hit50 = skin.animation("hit50").len();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(sliderb, 2);
+    assert_eq!(hit100, 3);
+    assert_eq!(hit50, 1);
+}
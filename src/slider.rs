@@ -0,0 +1,295 @@
+use glam::Vec2;
+use libosu::prelude::*;
+
+/// Flattened slider curve that can be sampled by distance travelled along it.
+#[derive(Debug, Clone)]
+pub struct SliderPath {
+    points: Vec<Vec2>,
+    /// Length of the path up to each point
+    lengths: Vec<f32>,
+}
+
+impl SliderPath {
+    pub fn new(object: &HitObject, slider: &SliderInfo) -> Self {
+        let mut control = Vec::with_capacity(slider.control_points.len() + 1);
+        control.push(object.pos);
+        control.extend(slider.control_points.iter());
+        let points =
+            libosu::spline::Spline::from_control(slider.kind, &control, Some(slider.pixel_length))
+                .spline_points
+                .into_iter()
+                .map(|p| glam::vec2(p.x as f32, p.y as f32))
+                .collect();
+        Self::from_points(points)
+    }
+
+    pub fn from_points(points: Vec<Vec2>) -> Self {
+        let lengths = points
+            .iter()
+            .scan(
+                (0.0, None),
+                |(length, prev): &mut (f32, Option<Vec2>), &p| {
+                    if let Some(prev) = prev {
+                        *length += (p - *prev).length();
+                    }
+                    *prev = Some(p);
+                    Some(*length)
+                },
+            )
+            .collect();
+        Self { points, lengths }
+    }
+
+    pub fn points(&self) -> &[Vec2] {
+        &self.points
+    }
+
+    pub fn length(&self) -> f32 {
+        self.lengths.last().copied().unwrap_or(0.0)
+    }
+
+    /// Position after travelling `progress` (0 at the head, 1 at the tail) of the path.
+    pub fn position_at(&self, progress: f32) -> Vec2 {
+        let distance = progress.clamp(0.0, 1.0) * self.length();
+        let i = self
+            .lengths
+            .iter()
+            .position(|&length| length >= distance)
+            .unwrap_or_else(|| self.lengths.len().saturating_sub(1));
+        if i == 0 {
+            return self.points.first().copied().unwrap_or(glam::vec2(0.0, 0.0));
+        }
+        let segment = self.lengths[i] - self.lengths[i - 1];
+        let t = if segment > 0.0 {
+            (distance - self.lengths[i - 1]) / segment
+        } else {
+            0.0
+        };
+        self.points[i - 1] + (self.points[i] - self.points[i - 1]) * t
+    }
+
+    /// Points of the part of the path between `from` and `to` progress.
+    pub fn sub_path(&self, from: f32, to: f32) -> Vec<Vec2> {
+        let (from, to) = (from.clamp(0.0, 1.0), to.clamp(0.0, 1.0));
+        let (start, end) = (from * self.length(), to * self.length());
+        std::iter::once(self.position_at(from))
+            .chain(
+                self.points
+                    .iter()
+                    .zip(&self.lengths)
+                    .filter(|(_, &length)| length > start && length < end)
+                    .map(|(&p, _)| p),
+            )
+            .chain(std::iter::once(self.position_at(to)))
+            .collect()
+    }
+}
+
+/// Time and milliseconds per beat of the uninherited timing point active at `time_ms`.
+fn uninherited_at(beatmap: &Beatmap, time_ms: i32) -> (i32, f64) {
+    let uninherited = beatmap
+        .timing_points
+        .iter()
+        .filter_map(|tp| match &tp.kind {
+            TimingPointKind::Uninherited(info) => Some((tp.time.0, info.mpb)),
+            _ => None,
+        });
+    uninherited
+        .clone()
+        .take_while(|&(time, _)| time <= time_ms)
+        .last()
+        .or_else(|| uninherited.clone().next())
+        .unwrap_or((0, 500.0))
+}
+
+/// Milliseconds per beat of the uninherited timing point active at `time_ms`.
+pub fn beat_length_at(beatmap: &Beatmap, time_ms: i32) -> f64 {
+    uninherited_at(beatmap, time_ms).1
+}
+
+/// How far `time_ms` is into the current beat, from 0 on the beat up to 1 at the next one.
+pub fn beat_progress_at(beatmap: &Beatmap, time_ms: i32) -> f32 {
+    let (beat_start, beat_length) = uninherited_at(beatmap, time_ms);
+    beat_progress(time_ms - beat_start, beat_length)
+}
+
+fn beat_progress(elapsed_ms: i32, beat_length: f64) -> f32 {
+    if beat_length <= 0.0 {
+        return 0.0;
+    }
+    (elapsed_ms as f64 / beat_length).rem_euclid(1.0) as f32
+}
+
+/// When a slider's ball is where, and when its ticks and repeats happen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SliderTiming {
+    pub start_ms: i32,
+    /// Number of times the ball travels the path, 1 for sliders without repeats
+    pub slides: u32,
+    pub slide_ms: f64,
+    pub tick_ms: f64,
+}
+
+impl SliderTiming {
+    pub fn new(beatmap: &Beatmap, object: &HitObject, slider: &SliderInfo) -> Self {
+        let slides = slider.num_repeats.max(1) as u32;
+        let duration = beatmap.get_slider_duration(object).unwrap_or(0.0);
+        let tick_rate = beatmap.difficulty.slider_tick_rate as f64;
+        Self {
+            start_ms: object.start_time.0,
+            slides,
+            slide_ms: duration / slides as f64,
+            tick_ms: if tick_rate > 0.0 {
+                beat_length_at(beatmap, object.start_time.0) / tick_rate
+            } else {
+                0.0
+            },
+        }
+    }
+
+    pub fn duration_ms(&self) -> f64 {
+        self.slide_ms * self.slides as f64
+    }
+
+    pub fn end_ms(&self) -> i32 {
+        self.start_ms + self.duration_ms() as i32
+    }
+
+    /// Index of the slide the ball is on at `time_ms`, clamped to the slider's lifetime.
+    pub fn slide_at(&self, time_ms: f64) -> u32 {
+        if self.slide_ms <= 0.0 {
+            return 0;
+        }
+        (((time_ms - self.start_ms as f64) / self.slide_ms).max(0.0) as u32).min(self.slides - 1)
+    }
+
+    /// Progress along the path (0 head, 1 tail) of the ball at `time_ms`,
+    /// travelling back towards the head on every other slide.
+    pub fn progress_at(&self, time_ms: f64) -> f32 {
+        if self.slide_ms <= 0.0 {
+            return 0.0;
+        }
+        let elapsed = (time_ms - self.start_ms as f64).clamp(0.0, self.duration_ms());
+        let slide = self.slide_at(time_ms);
+        let t = ((elapsed - slide as f64 * self.slide_ms) / self.slide_ms).clamp(0.0, 1.0) as f32;
+        if slide % 2 == 1 {
+            1.0 - t
+        } else {
+            t
+        }
+    }
+
+    /// Times of the repeat points, where the ball turns around.
+    pub fn repeat_times(&self) -> Vec<f64> {
+        (1..self.slides)
+            .map(|slide| self.start_ms as f64 + slide as f64 * self.slide_ms)
+            .collect()
+    }
+
+    /// Whether the ball still has to turn around at the head and at the tail after `time_ms`.
+    pub fn upcoming_repeats(&self, time_ms: f64) -> (bool, bool) {
+        self.repeat_times()
+            .iter()
+            .enumerate()
+            .filter(|(_, &repeat_ms)| repeat_ms > time_ms)
+            // The first repeat is at the tail, then they alternate
+            .fold((false, false), |(head, tail), (i, _)| {
+                (head || i % 2 == 1, tail || i % 2 == 0)
+            })
+    }
+
+    /// Times of every slider tick, in order.
+    pub fn tick_times(&self) -> Vec<f64> {
+        if self.tick_ms <= 0.0 {
+            return Vec::new();
+        }
+        // Ticks too close to the end of a slide are dropped, like the game does
+        let offsets: Vec<f64> = (1..)
+            .map(|i| i as f64 * self.tick_ms)
+            .take_while(|&offset| offset < self.slide_ms - 10.0)
+            .collect();
+        (0..self.slides)
+            .flat_map(|slide| {
+                let slide_start = self.start_ms as f64 + slide as f64 * self.slide_ms;
+                let reversed = slide % 2 == 1;
+                let slide_ms = self.slide_ms;
+                let mut times: Vec<f64> = offsets
+                    .iter()
+                    .map(|&offset| slide_start + if reversed { slide_ms - offset } else { offset })
+                    .collect();
+                times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                times
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_slider_path_position() {
+    let path = SliderPath::from_points(vec![
+        glam::vec2(0.0, 0.0),
+        glam::vec2(100.0, 0.0),
+        glam::vec2(100.0, 100.0),
+    ]);
+    assert_eq!(path.length(), 200.0);
+    assert_eq!(path.position_at(0.0), glam::vec2(0.0, 0.0));
+    assert_eq!(path.position_at(0.25), glam::vec2(50.0, 0.0));
+    assert_eq!(path.position_at(0.75), glam::vec2(100.0, 50.0));
+    assert_eq!(path.position_at(1.0), glam::vec2(100.0, 100.0));
+    assert_eq!(
+        path.sub_path(0.25, 0.75),
+        vec![
+            glam::vec2(50.0, 0.0),
+            glam::vec2(100.0, 0.0),
+            glam::vec2(100.0, 50.0)
+        ]
+    );
+}
+
+#[test]
+fn test_slider_timing() {
+    let timing = SliderTiming {
+        start_ms: 1000,
+        slides: 2,
+        slide_ms: 400.0,
+        tick_ms: 100.0,
+    };
+    assert_eq!(timing.end_ms(), 1800);
+    assert_eq!(timing.progress_at(1000.0), 0.0);
+    assert_eq!(timing.progress_at(1200.0), 0.5);
+    assert_eq!(timing.progress_at(1400.0), 1.0);
+    assert_eq!(timing.progress_at(1500.0), 0.75);
+    assert_eq!(timing.progress_at(1800.0), 0.0);
+    assert_eq!(timing.slide_at(1500.0), 1);
+    assert_eq!(timing.repeat_times(), vec![1400.0]);
+    assert_eq!(timing.upcoming_repeats(1000.0), (false, true));
+    assert_eq!(timing.upcoming_repeats(1400.0), (false, false));
+    assert_eq!(
+        timing.tick_times(),
+        vec![1100.0, 1200.0, 1300.0, 1500.0, 1600.0, 1700.0]
+    );
+}
+
+#[test]
+fn test_upcoming_repeats() {
+    let timing = SliderTiming {
+        start_ms: 0,
+        slides: 4,
+        slide_ms: 100.0,
+        tick_ms: 0.0,
+    };
+    assert_eq!(timing.upcoming_repeats(0.0), (true, true));
+    assert_eq!(timing.upcoming_repeats(150.0), (true, true));
+    assert_eq!(timing.upcoming_repeats(250.0), (false, true));
+    assert_eq!(timing.upcoming_repeats(350.0), (false, false));
+}
+
+#[test]
+fn test_beat_progress() {
+    assert_eq!(beat_progress(0, 500.0), 0.0);
+    assert_eq!(beat_progress(250, 500.0), 0.5);
+    assert_eq!(beat_progress(1125, 500.0), 0.25);
+    // Before the first timing point
+    assert_eq!(beat_progress(-125, 500.0), 0.75);
+    assert_eq!(beat_progress(100, 0.0), 0.0);
+}
@@ -0,0 +1,214 @@
+use std::f32::consts::{PI, TAU};
+
+use libosu::{prelude::Buttons, replay::ReplayAction};
+
+use crate::helper::od_to_spins_per_second;
+
+/// Fastest a spinner can be spun, in rotations per minute. Anything faster is capped to this.
+pub const MAX_RPM: f32 = 477.0;
+
+/// How far back the RPM display looks to average out the spinning speed, in milliseconds.
+const RPM_WINDOW_MS: i32 = 500;
+
+/// Spins needed to clear a spinner lasting `duration_ms`.
+pub fn required_spins(od: f32, duration_ms: i32) -> u32 {
+    (duration_ms as f32 / 1000.0 * od_to_spins_per_second(od)) as u32
+}
+
+/// How a spinner was spun over its lifetime, worked out from the replay's cursor movement around the playfield centre.
+/// Times are beatmap times, so with a `clock_rate` other than 1 the spinner is spun faster or slower in real time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpinnerSpins {
+    start_ms: i32,
+    clock_rate: f64,
+    /// Spins needed to clear the spinner
+    required: u32,
+    /// Time of each replay frame during the spinner, the total rotation counted towards spins
+    /// and the spinner's rotation in the direction it was spun, both in radians
+    samples: Vec<(i32, f32, f32)>,
+}
+
+impl SpinnerSpins {
+    pub fn new(
+        frames: &[(i32, ReplayAction)],
+        start_ms: i32,
+        end_ms: i32,
+        clock_rate: f64,
+        required: u32,
+    ) -> Self {
+        let center = glam::vec2(256.0, 192.0);
+        // The cap is in real time, which passes faster than beatmap time with DoubleTime
+        let max_speed = MAX_RPM / 60_000.0 * TAU / clock_rate as f32;
+        let first = frames.partition_point(|(time, _)| *time < start_ms);
+
+        let mut samples = Vec::new();
+        let mut prev: Option<(i32, f32)> = None;
+        let (mut total, mut rotation) = (0.0, 0.0);
+        for (time, action) in frames[first..]
+            .iter()
+            .take_while(|(time, _)| *time <= end_ms)
+        {
+            let offset = glam::vec2(action.x, action.y) - center;
+            let angle = offset.y.atan2(offset.x);
+            if let Some((prev_time, prev_angle)) = prev {
+                // Shortest way around from the previous angle
+                let mut delta = angle - prev_angle;
+                if delta > PI {
+                    delta -= TAU;
+                } else if delta < -PI {
+                    delta += TAU;
+                }
+                let max_delta = max_speed * (time - prev_time) as f32;
+                let delta = delta.clamp(-max_delta, max_delta);
+                // The cursor is followed all the time, but only counts while a key is held
+                if action.buttons.intersects(Buttons::M1 | Buttons::M2) {
+                    total += delta.abs();
+                    rotation += delta;
+                }
+            }
+            prev = Some((*time, angle));
+            samples.push((*time, total, rotation));
+        }
+        Self {
+            start_ms,
+            clock_rate,
+            required,
+            samples,
+        }
+    }
+
+    /// Total rotation and spinner rotation at `time_ms`, interpolated between frames.
+    fn sample(&self, time_ms: i32) -> (f32, f32) {
+        let next = self.samples.partition_point(|(time, ..)| *time <= time_ms);
+        match (
+            next.checked_sub(1).map(|i| self.samples[i]),
+            self.samples.get(next),
+        ) {
+            (
+                Some((prev_time, prev_total, prev_rotation)),
+                Some(&(next_time, next_total, next_rotation)),
+            ) => {
+                let t = (time_ms - prev_time) as f32 / (next_time - prev_time) as f32;
+                (
+                    prev_total + (next_total - prev_total) * t,
+                    prev_rotation + (next_rotation - prev_rotation) * t,
+                )
+            }
+            (Some((_, total, rotation)), None) => (total, rotation),
+            (None, _) => (0.0, 0.0),
+        }
+    }
+
+    /// Spins needed to clear the spinner.
+    pub fn required(&self) -> u32 {
+        self.required
+    }
+
+    /// Number of spins by `time_ms`, including partial ones.
+    pub fn spins_at(&self, time_ms: i32) -> f32 {
+        self.sample(time_ms).0 / TAU
+    }
+
+    /// Angle the spinner is turned to at `time_ms`, in radians.
+    pub fn rotation_at(&self, time_ms: i32) -> f32 {
+        self.sample(time_ms).1
+    }
+
+    /// Full spins past the ones needed to clear the spinner by `time_ms`, each worth a bonus.
+    pub fn bonus_spins_at(&self, time_ms: i32) -> u32 {
+        (self.spins_at(time_ms) as u32).saturating_sub(self.required)
+    }
+
+    /// Times at which each full spin was completed, interpolated between frames.
+    pub fn spin_times(&self) -> Vec<i32> {
+        let mut times = Vec::new();
+        for pair in self.samples.windows(2) {
+            let ((prev_time, prev_total, _), (time, total, _)) = (pair[0], pair[1]);
+            loop {
+                let target = (times.len() + 1) as f32 * TAU;
+                if target > total {
+                    break;
+                }
+                let t = (target - prev_total) / (total - prev_total);
+                times.push(prev_time + ((time - prev_time) as f32 * t).round() as i32);
+            }
+        }
+        times
+    }
+
+    /// Spinning speed in real time at `time_ms`, averaged over the last moments.
+    pub fn rpm_at(&self, time_ms: i32) -> f32 {
+        let window_start = (time_ms - RPM_WINDOW_MS).max(self.start_ms);
+        if time_ms <= window_start {
+            return 0.0;
+        }
+        let spins = self.spins_at(time_ms) - self.spins_at(window_start);
+        spins * 60_000.0 * self.clock_rate as f32 / (time_ms - window_start) as f32
+    }
+}
+
+/// A frame with the cursor `angle` radians around the playfield centre.
+#[cfg(test)]
+pub fn spin_frame(time: i32, angle: f32, buttons: Buttons) -> (i32, ReplayAction) {
+    crate::sampler::frame(
+        time,
+        256.0 + angle.cos() * 100.0,
+        192.0 + angle.sin() * 100.0,
+        buttons,
+    )
+}
+
+#[test]
+fn test_required_spins() {
+    assert_eq!(required_spins(5.0, 2000), 10);
+    assert_eq!(required_spins(10.0, 1000), 7);
+}
+
+#[test]
+fn test_spinner_spins() {
+    let held = Buttons::K1 | Buttons::M1;
+    // A quarter turn every 50ms is 300 RPM, and one frame without a key held
+    let frames: Vec<_> = (0..=10)
+        .map(|i| {
+            let buttons = if i == 5 { Buttons::empty() } else { held };
+            spin_frame(1000 + i * 50, i as f32 * PI / 2.0, buttons)
+        })
+        .collect();
+    let spins = SpinnerSpins::new(&frames, 1000, 1500, 1.0, 1);
+    assert!((spins.spins_at(1500) - 2.25).abs() < 1e-4);
+    assert!((spins.spins_at(1225) - 1.0).abs() < 1e-4);
+    assert!((spins.rotation_at(1100) - PI).abs() < 1e-4);
+    assert!((spins.rpm_at(1200) - 300.0).abs() < 1e-2);
+    assert_eq!(spins.spins_at(500), 0.0);
+    assert_eq!(spins.bonus_spins_at(1500), 1);
+    let harder = SpinnerSpins {
+        required: 3,
+        ..spins.clone()
+    };
+    assert_eq!(harder.bonus_spins_at(1500), 0);
+    assert_eq!(spins.spin_times(), vec![1200, 1450]);
+}
+
+#[test]
+fn test_spinner_max_rpm() {
+    let held = Buttons::K1 | Buttons::M1;
+    // Half a turn every 16ms is way above what the game allows
+    let frames: Vec<_> = (0..=60)
+        .map(|i| spin_frame(i * 16, i as f32 * (PI - 0.01), held))
+        .collect();
+    let spins = SpinnerSpins::new(&frames, 0, 960, 1.0, 0);
+    assert!((spins.rpm_at(960) - MAX_RPM).abs() < 1e-2);
+}
+
+#[test]
+fn test_spinner_max_rpm_double_time() {
+    let held = Buttons::K1 | Buttons::M1;
+    let frames: Vec<_> = (0..=60)
+        .map(|i| spin_frame(i * 16, i as f32 * (PI - 0.01), held))
+        .collect();
+    let nomod = SpinnerSpins::new(&frames, 0, 960, 1.0, 0);
+    // The same beatmap time passes in two thirds of the real time, so fewer spins fit in it
+    let double_time = SpinnerSpins::new(&frames, 0, 960, 1.5, 0);
+    assert!((double_time.rpm_at(960) - MAX_RPM).abs() < 1e-2);
+    assert!((double_time.spins_at(960) - nomod.spins_at(960) / 1.5).abs() < 1e-3);
+}